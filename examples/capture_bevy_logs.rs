@@ -1,14 +1,15 @@
 use bevy::log::LogPlugin;
 use bevy::{log, prelude::*};
-use bevy_console::{make_layer, ConsolePlugin};
+use bevy_console::{make_filtered_layer, ConsolePlugin};
 
 fn main() {
     App::new()
         .add_plugins((
             DefaultPlugins.set(LogPlugin {
-                level: log::Level::INFO,
-                filter: "error,capture_bevy_logs=info".to_owned(),
-                custom_layer: make_layer,
+                // Let everything through, the console filter decides what is logged
+                level: log::Level::TRACE,
+                filter: String::new(),
+                custom_layer: |app| make_filtered_layer(app, "error,capture_bevy_logs=info".into()),
             }),
             ConsolePlugin,
         ))
@@ -1,31 +1,241 @@
 use std::collections::HashSet;
+use std::mem;
+use std::ops::Range;
 
-use ansi_parser::AnsiParser;
-use bevy_egui::egui::Color32;
+use ansi_parser::AnsiSequence;
+use bevy_egui::egui::text::{LayoutJob, LayoutSection};
+use bevy_egui::egui::{Color32, TextFormat};
 
-pub(crate) fn parse_ansi_styled_str(
-    ansi_string: &str,
-) -> Vec<(&str, HashSet<TextFormattingOverride>)> {
+/// Default value of [`ConsoleConfiguration::ansi_colors`](crate::ConsoleConfiguration::ansi_colors)
+pub(crate) const DEFAULT_ANSI_COLORS: [Color32; 16] = [
+    Color32::from_rgb(1, 1, 1),       // black
+    Color32::from_rgb(222, 56, 43),   // red
+    Color32::from_rgb(57, 181, 74),   // green
+    Color32::from_rgb(255, 199, 6),   // yellow
+    Color32::from_rgb(0, 111, 184),   // blue
+    Color32::from_rgb(118, 38, 113),  // magenta
+    Color32::from_rgb(44, 181, 233),  // cyan
+    Color32::from_rgb(204, 204, 204), // white
+    Color32::from_rgb(128, 128, 128), // bright black
+    Color32::from_rgb(255, 0, 0),     // bright red
+    Color32::from_rgb(0, 255, 0),     // bright green
+    Color32::from_rgb(255, 255, 0),   // bright yellow
+    Color32::from_rgb(0, 0, 255),     // bright blue
+    Color32::from_rgb(255, 0, 255),   // bright magenta
+    Color32::from_rgb(0, 255, 255),   // bright cyan
+    Color32::from_rgb(255, 255, 255), // bright white
+];
+
+/// Splits a string into text runs and the styling applied to each of them.
+///
+/// `palette` holds the 16 standard and bright colors, which also start the 256 color palette.
+pub(crate) fn parse_ansi_styled_str<'a>(
+    ansi_string: &'a str,
+    palette: &[Color32; 16],
+) -> Vec<(&'a str, HashSet<TextFormattingOverride>)> {
     let mut result: Vec<(&str, HashSet<TextFormattingOverride>)> = Vec::new();
     let mut current_overrides = HashSet::new();
-    for element in ansi_string.ansi_parse() {
-        match element {
-            ansi_parser::Output::TextBlock(t) => {
+    for token in tokenize(ansi_string) {
+        match token {
+            Token::Text(t) => {
                 result.push((t, current_overrides.clone()));
             }
-            ansi_parser::Output::Escape(escape) => {
-                if let ansi_parser::AnsiSequence::SetGraphicsMode(mode) = escape {
-                    let modes = parse_graphics_mode(mode.as_slice());
-                    for m in modes.iter() {
-                        apply_set_graphics_mode(&mut current_overrides, *m);
-                    }
+            Token::Csi {
+                params,
+                final_byte: 'm',
+            } => {
+                for mode in parse_graphics_mode(params, palette) {
+                    apply_set_graphics_mode(&mut current_overrides, mode);
                 }
             }
+            Token::Escape(_) | Token::Csi { .. } | Token::Link(_) => {}
         }
     }
     result
 }
 
+/// Text without ANSI escape sequences, as shown in the console.
+pub(crate) fn plain_text(ansi_string: &str) -> String {
+    tokenize(ansi_string)
+        .filter_map(|token| match token {
+            Token::Text(text) => Some(text),
+            Token::Escape(_) | Token::Csi { .. } | Token::Link(_) => None,
+        })
+        .collect()
+}
+
+/// Piece of a string containing ANSI escape sequences
+#[derive(Debug, PartialEq)]
+pub(crate) enum Token<'a> {
+    Text(&'a str),
+    /// Escape sequence known to `ansi_parser`, other than select graphic rendition
+    Escape(AnsiSequence),
+    /// Other control sequence, e.g. `ESC[1;38;5;208m` with params `1;38;5;208` and final byte `m`
+    Csi {
+        params: &'a str,
+        final_byte: char,
+    },
+    /// OSC 8 hyperlink to the given URI, an empty URI ends the link
+    Link(&'a str),
+}
+
+/// Splits a string into text, control sequences and hyperlinks.
+///
+/// Other escape sequences, like other operating system commands, are dropped.
+pub(crate) fn tokenize(ansi_string: &str) -> impl Iterator<Item = Token<'_>> {
+    let mut rest = ansi_string;
+    std::iter::from_fn(move || loop {
+        match rest.find('\u{1b}') {
+            Some(0) => {
+                let (token, len) = parse_escape(rest);
+                rest = &rest[len..];
+                if token.is_some() {
+                    return token;
+                }
+            }
+            Some(start) => {
+                let (text, remaining) = rest.split_at(start);
+                rest = remaining;
+                return Some(Token::Text(text));
+            }
+            None if rest.is_empty() => return None,
+            None => return Some(Token::Text(std::mem::take(&mut rest))),
+        }
+    })
+}
+
+/// Parses the escape sequence at the start of `sequence`, returning it and its length in bytes.
+///
+/// Select graphic rendition sequences and sequences `ansi_parser` doesn't know are returned as
+/// [`Token::Csi`], `ansi_parser` only accepts a few graphics parameters. Unknown sequences are
+/// skipped up to their final byte, or to the end of `sequence` if they are truncated.
+fn parse_escape(sequence: &str) -> (Option<Token<'_>>, usize) {
+    let bytes = sequence.as_bytes();
+    let sgr = bytes.get(1) == Some(&b'[')
+        && bytes[2..].iter().find(|b| !(0x20..=0x3f).contains(*b)) == Some(&b'm');
+    if !sgr {
+        if let Ok((rest, escape)) = ansi_parser::parse_escape(sequence) {
+            return (Some(Token::Escape(escape)), sequence.len() - rest.len());
+        }
+    }
+    match bytes.get(1) {
+        Some(b'[') => {
+            // Parameter and intermediate bytes, followed by a final byte
+            let end = bytes[2..]
+                .iter()
+                .position(|b| !(0x20..=0x3f).contains(b))
+                .map_or(bytes.len(), |i| i + 2);
+            match bytes.get(end) {
+                Some(final_byte @ 0x40..=0x7e) => (
+                    Some(Token::Csi {
+                        params: &sequence[2..end],
+                        final_byte: *final_byte as char,
+                    }),
+                    end + 1,
+                ),
+                _ => (None, end),
+            }
+        }
+        Some(b']') => {
+            // Operating system command, terminated by BEL or ST
+            let Some(end) = sequence[2..].find(['\u{7}', '\u{1b}']).map(|i| i + 2) else {
+                return (None, sequence.len());
+            };
+            let terminator = if bytes[end] == 0x07 { 1 } else { 2 };
+            let len = (end + terminator).min(sequence.len());
+            // `ESC]8;params;URI ST`, where params are optional
+            let link = sequence[2..end]
+                .strip_prefix("8;")
+                .and_then(|link| link.split_once(';'))
+                .map(|(_, uri)| Token::Link(uri));
+            (link, len)
+        }
+        Some(_) => {
+            let next = sequence[1..].chars().next().map_or(0, char::len_utf8);
+            (None, 1 + next)
+        }
+        None => (None, 1),
+    }
+}
+
+/// Whether a control sequence moves the cursor or erases text, as handled by the scrollback.
+fn is_cursor_control(token: &Token<'_>) -> bool {
+    match token {
+        Token::Escape(escape) => matches!(
+            escape,
+            AnsiSequence::CursorUp(_) | AnsiSequence::CursorDown(_) | AnsiSequence::EraseLine
+        ),
+        Token::Csi { final_byte, .. } => matches!(final_byte, 'A' | 'B' | 'E' | 'F' | 'K'),
+        Token::Text(_) | Token::Link(_) => false,
+    }
+}
+
+/// Whether the text contains carriage returns or cursor controls.
+pub(crate) fn has_cursor_controls(text: &str) -> bool {
+    text.contains('\r') || tokenize(text).any(|token| is_cursor_control(&token))
+}
+
+/// Length in bytes of the carriage returns and cursor controls `text` starts with.
+pub(crate) fn leading_cursor_controls(text: &str) -> usize {
+    let mut len = 0;
+    loop {
+        let rest = &text[len..];
+        if rest.starts_with('\r') {
+            len += 1;
+        } else if rest.starts_with('\u{1b}') {
+            match parse_escape(rest) {
+                (Some(token), escape_len) if is_cursor_control(&token) => len += escape_len,
+                _ => return len,
+            }
+        } else {
+            return len;
+        }
+    }
+}
+
+/// Splits the sections of `job` so `restyle` can change the format of the given sorted byte
+/// ranges, getting the index of the range along with the format.
+pub(crate) fn restyle_ranges(
+    job: &mut LayoutJob,
+    ranges: &[Range<usize>],
+    mut restyle: impl FnMut(usize, &mut TextFormat),
+) {
+    let mut sections = Vec::with_capacity(job.sections.len() + ranges.len() * 2);
+    for section in job.sections.drain(..) {
+        let mut start = section.byte_range.start;
+        let end = section.byte_range.end;
+        let mut leading_space = section.leading_space;
+        let mut push = |byte_range: Range<usize>, range_index: Option<usize>| {
+            let mut format = section.format.clone();
+            if let Some(range_index) = range_index {
+                restyle(range_index, &mut format);
+            }
+            sections.push(LayoutSection {
+                leading_space: mem::take(&mut leading_space),
+                byte_range,
+                format,
+            });
+        };
+
+        for (i, range) in ranges.iter().enumerate() {
+            let from = range.start.max(start);
+            let to = range.end.min(end);
+            if from >= to {
+                continue;
+            }
+            if start < from {
+                push(start..from, None);
+            }
+            push(from..to, Some(i));
+            start = to;
+        }
+        if start < end {
+            push(start..end, None);
+        }
+    }
+    job.sections = sections;
+}
+
 fn apply_set_graphics_mode(
     set_overrides: &mut HashSet<TextFormattingOverride>,
     new: TextFormattingOverride,
@@ -53,16 +263,35 @@ fn apply_set_graphics_mode(
         TextFormattingOverride::ResetBackgroundColor => {
             set_overrides.retain(|o| !matches!(o, TextFormattingOverride::Background(_)));
         }
+        TextFormattingOverride::Foreground(_) => {
+            set_overrides.retain(|o| !matches!(o, TextFormattingOverride::Foreground(_)));
+            set_overrides.insert(new);
+        }
+        TextFormattingOverride::Background(_) => {
+            set_overrides.retain(|o| !matches!(o, TextFormattingOverride::Background(_)));
+            set_overrides.insert(new);
+        }
         _ => {
             set_overrides.insert(new);
         }
     }
 }
 
-fn parse_graphics_mode(modes: &[u8]) -> HashSet<TextFormattingOverride> {
-    let mut results = HashSet::new();
-    for mode in modes.iter() {
-        let result = match *mode {
+/// Parses the parameters of a select graphic rendition sequence, ignoring unsupported codes.
+fn parse_graphics_mode(params: &str, palette: &[Color32; 16]) -> Vec<TextFormattingOverride> {
+    let mut results = Vec::new();
+    let mut parts = params.split(';');
+    while let Some(part) = parts.next() {
+        let mut subparams = part.split(':');
+        let mode = match subparams.next() {
+            Some("") | None => 0,
+            Some(mode) => match mode.parse::<u16>() {
+                Ok(mode) => mode,
+                Err(_) => continue,
+            },
+        };
+        let result = match mode {
+            0 => TextFormattingOverride::ResetEveryting,
             1 => TextFormattingOverride::Bold,
             2 => TextFormattingOverride::Dim,
             3 => TextFormattingOverride::Italic,
@@ -72,35 +301,71 @@ fn parse_graphics_mode(modes: &[u8]) -> HashSet<TextFormattingOverride> {
             23 => TextFormattingOverride::ResetItalicsAndFraktur,
             24 => TextFormattingOverride::ResetUnderline,
             29 => TextFormattingOverride::ResetStrikethrough,
-            30..=37 => TextFormattingOverride::Foreground(ansi_color_code_to_color32(mode - 30)),
+            30..=37 => TextFormattingOverride::Foreground(palette[usize::from(mode - 30)]),
             39 => TextFormattingOverride::ResetForegroundColor,
-            40..=47 => TextFormattingOverride::Background(ansi_color_code_to_color32(mode - 40)),
+            40..=47 => TextFormattingOverride::Background(palette[usize::from(mode - 40)]),
             49 => TextFormattingOverride::ResetBackgroundColor,
-            _ => TextFormattingOverride::ResetEveryting,
+            90..=97 => TextFormattingOverride::Foreground(palette[usize::from(mode - 90 + 8)]),
+            100..=107 => TextFormattingOverride::Background(palette[usize::from(mode - 100 + 8)]),
+            38 | 48 => {
+                // Either `38;5;n` or the `38:5:n` form with subparameters
+                let color = if part.contains(':') {
+                    extended_color(&mut subparams, true, palette)
+                } else {
+                    extended_color(&mut parts, false, palette)
+                };
+                match (mode, color) {
+                    (38, Some(color)) => TextFormattingOverride::Foreground(color),
+                    (_, Some(color)) => TextFormattingOverride::Background(color),
+                    (_, None) => continue,
+                }
+            }
+            _ => continue,
         };
-        results.insert(result);
+        results.push(result);
     }
     results
 }
 
-fn ansi_color_code_to_color32(color_code: u8) -> Color32 {
-    match color_code {
-        1 => Color32::from_rgb(222, 56, 43),    // red
-        2 => Color32::from_rgb(57, 181, 74),    // green
-        3 => Color32::from_rgb(255, 199, 6),    // yellow
-        4 => Color32::from_rgb(0, 111, 184),    // blue
-        5 => Color32::from_rgb(118, 38, 113),   // magenta
-        6 => Color32::from_rgb(44, 181, 233),   // cyan
-        7 => Color32::from_rgb(204, 204, 204),  // white
-        8 => Color32::from_rgb(128, 128, 128),  // bright black
-        9 => Color32::from_rgb(255, 0, 0),      // bright red
-        10 => Color32::from_rgb(0, 255, 0),     // bright green
-        11 => Color32::from_rgb(255, 255, 0),   // bright yellow
-        12 => Color32::from_rgb(0, 0, 255),     // bright blue
-        13 => Color32::from_rgb(255, 0, 255),   // bright magenta
-        14 => Color32::from_rgb(0, 255, 255),   // bright cyan
-        15 => Color32::from_rgb(255, 255, 255), // bright white
-        _ => Color32::from_rgb(1, 1, 1),        // black
+/// Parses the color following 38 or 48, either `5;n` from the 256 color palette or `2;r;g;b`.
+///
+/// The subparameter form may contain a color space id before the components, `2::r:g:b`.
+fn extended_color<'a>(
+    args: &mut impl Iterator<Item = &'a str>,
+    subparams: bool,
+    palette: &[Color32; 16],
+) -> Option<Color32> {
+    let mut next = || args.next()?.parse::<u8>().ok();
+    match next()? {
+        5 => Some(indexed_color(next()?, palette)),
+        2 if subparams => {
+            let components = args.collect::<Vec<_>>();
+            let rgb = match components.as_slice() {
+                [_, r, g, b, ..] => [*r, *g, *b],
+                [r, g, b] => [*r, *g, *b],
+                _ => return None,
+            };
+            let [r, g, b] = rgb.map(|c| c.parse::<u8>().ok());
+            Some(Color32::from_rgb(r?, g?, b?))
+        }
+        2 => Some(Color32::from_rgb(next()?, next()?, next()?)),
+        _ => None,
+    }
+}
+
+/// Color of the 256 color palette: 16 named colors, a 6x6x6 cube and 24 shades of gray.
+fn indexed_color(index: u8, palette: &[Color32; 16]) -> Color32 {
+    match index {
+        0..=15 => palette[usize::from(index)],
+        16..=231 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let index = index - 16;
+            Color32::from_rgb(level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        232..=255 => {
+            let gray = 8 + (index - 232) * 10;
+            Color32::from_rgb(gray, gray, gray)
+        }
     }
 }
 
@@ -126,6 +391,10 @@ pub(crate) enum TextFormattingOverride {
 mod test {
     use super::*;
 
+    fn parse_ansi_styled_str(ansi_string: &str) -> Vec<(&str, HashSet<TextFormattingOverride>)> {
+        super::parse_ansi_styled_str(ansi_string, &DEFAULT_ANSI_COLORS)
+    }
+
     #[test]
     fn test_bold_text() {
         let ansi_string = color_print::cstr!(r#"<bold>12345</bold>"#);
@@ -330,4 +599,182 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn test_plain_text() {
+        let example = "\u{1b}[2m2025-01-03T19:20:08.083551Z\u{1b}[0m \u{1b}[32m INFO\u{1b}[0m Bye!";
+
+        assert_eq!(
+            plain_text(example),
+            "2025-01-03T19:20:08.083551Z  INFO Bye!"
+        );
+    }
+
+    #[test]
+    fn test_bright_colors() {
+        let result = parse_ansi_styled_str("\u{1b}[91;104mx");
+        assert_eq!(
+            result,
+            vec![(
+                "x",
+                HashSet::from([
+                    TextFormattingOverride::Foreground(Color32::from_rgb(255, 0, 0)),
+                    TextFormattingOverride::Background(Color32::from_rgb(0, 0, 255)),
+                ])
+            )]
+        );
+    }
+
+    #[test]
+    fn test_256_colors() {
+        let colors = [
+            (1, (222, 56, 43)),
+            (16, (0, 0, 0)),
+            (196, (255, 0, 0)),
+            (244, (128, 128, 128)),
+        ];
+        for (index, (r, g, b)) in colors {
+            let ansi_string = format!("\u{1b}[38;5;{index}mx");
+            assert_eq!(
+                parse_ansi_styled_str(&ansi_string),
+                vec![(
+                    "x",
+                    HashSet::from([TextFormattingOverride::Foreground(Color32::from_rgb(
+                        r, g, b
+                    ))])
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn test_truecolor() {
+        assert_eq!(
+            parse_ansi_styled_str("\u{1b}[38;2;10;20;30mx"),
+            vec![(
+                "x",
+                HashSet::from([TextFormattingOverride::Foreground(Color32::from_rgb(
+                    10, 20, 30
+                ))])
+            )]
+        );
+        let expected = HashSet::from([
+            TextFormattingOverride::Bold,
+            TextFormattingOverride::Background(Color32::from_rgb(10, 20, 30)),
+        ]);
+        for ansi_string in [
+            "\u{1b}[1;48;2;10;20;30mx",
+            "\u{1b}[1;48:2:10:20:30mx",
+            "\u{1b}[1;48:2::10:20:30mx",
+        ] {
+            assert_eq!(
+                parse_ansi_styled_str(ansi_string),
+                vec![("x", expected.clone())]
+            );
+        }
+    }
+
+    #[test]
+    fn test_combined_color_parameters() {
+        let result = parse_ansi_styled_str("\u{1b}[1;38;5;196mx\u{1b}[38;5;1;48;5;2my");
+        let red = Color32::from_rgb(255, 0, 0);
+        assert_eq!(
+            result,
+            vec![
+                (
+                    "x",
+                    HashSet::from([
+                        TextFormattingOverride::Bold,
+                        TextFormattingOverride::Foreground(red),
+                    ])
+                ),
+                (
+                    "y",
+                    HashSet::from([
+                        TextFormattingOverride::Bold,
+                        TextFormattingOverride::Foreground(DEFAULT_ANSI_COLORS[1]),
+                        TextFormattingOverride::Background(DEFAULT_ANSI_COLORS[2]),
+                    ])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_sequences_are_dropped() {
+        let bold = HashSet::from([TextFormattingOverride::Bold]);
+        for ansi_string in [
+            "\u{1b}[1mx\u{1b}[38;5m",
+            "\u{1b}[1mx\u{1b}[38;2;1;2m",
+            "\u{1b}[1mx\u{1b}[38;5;300m",
+            "\u{1b}[1mx\u{1b}[31",
+            "\u{1b}[1mx\u{1b}[",
+            "\u{1b}[1mx\u{1b}",
+        ] {
+            assert_eq!(
+                parse_ansi_styled_str(ansi_string),
+                vec![("x", bold.clone())],
+                "{ansi_string:?}"
+            );
+        }
+        assert_eq!(plain_text("a\u{1b}[38:5:1mb\u{1b}[1;2mc\u{1b}]8;;x"), "abc");
+    }
+
+    #[test]
+    fn test_unknown_codes_keep_styling() {
+        let result = parse_ansi_styled_str("\u{1b}[1mbold\u{1b}[5m still bold");
+        assert_eq!(
+            result,
+            vec![
+                ("bold", HashSet::from([TextFormattingOverride::Bold])),
+                (" still bold", HashSet::from([TextFormattingOverride::Bold])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_colors_replace_each_other() {
+        let result = parse_ansi_styled_str("\u{1b}[31m\u{1b}[32mgreen");
+        assert_eq!(
+            result,
+            vec![(
+                "green",
+                HashSet::from([TextFormattingOverride::Foreground(Color32::from_rgb(
+                    57, 181, 74
+                ))])
+            )]
+        );
+    }
+
+    #[test]
+    fn test_palette_is_configurable() {
+        let mut palette = DEFAULT_ANSI_COLORS;
+        palette[1] = Color32::from_rgb(1, 2, 3);
+        let result = super::parse_ansi_styled_str("\u{1b}[31mx", &palette);
+        assert_eq!(
+            result,
+            vec![(
+                "x",
+                HashSet::from([TextFormattingOverride::Foreground(Color32::from_rgb(
+                    1, 2, 3
+                ))])
+            )]
+        );
+    }
+
+    #[test]
+    fn test_cursor_controls() {
+        assert!(has_cursor_controls("50%\r"));
+        assert!(has_cursor_controls("\u{1b}[1A\u{1b}[2Kdone"));
+        assert!(!has_cursor_controls("\u{1b}[1mdone\u{1b}[0m"));
+
+        assert_eq!(leading_cursor_controls("\r\u{1b}[2K\u{1b}[1mx\r"), 5);
+        assert_eq!(leading_cursor_controls("x\r"), 0);
+    }
+
+    #[test]
+    fn test_other_sequences_are_dropped() {
+        let example = "a\u{1b}[2Kb\u{1b}]0;title\u{7}c\u{1b}]8;;https://x\u{1b}\\d\u{1b}7e\u{1b}";
+        assert_eq!(plain_text(example), "abcde");
+    }
 }
@@ -0,0 +1,33 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use clap::Parser;
+
+use crate as bevy_console;
+use crate::commands::timestamped_path;
+use crate::console::ConsoleState;
+use crate::{reply_failed, reply_ok, ConsoleCommand};
+
+/// Writes the scrollback to a file, without colors
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "condump")]
+pub(crate) struct CondumpCommand {
+    /// File to write, defaults to `condump_<unix time>.txt` in the working directory
+    file: Option<PathBuf>,
+}
+
+pub(crate) fn condump_command(
+    mut condump: ConsoleCommand<CondumpCommand>,
+    state: Res<ConsoleState>,
+) {
+    if let Some(Ok(CondumpCommand { file })) = condump.take() {
+        let path = file.unwrap_or_else(|| timestamped_path("condump", "txt"));
+
+        match File::create(&path).and_then(|file| state.scrollback.dump(BufWriter::new(file))) {
+            Ok(lines) => reply_ok!(condump, "Wrote {lines} lines to {}", path.display()),
+            Err(err) => reply_failed!(condump, "Failed to write {}: {err}", path.display()),
+        }
+    }
+}
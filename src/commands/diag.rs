@@ -0,0 +1,209 @@
+use bevy::diagnostic::{Diagnostic, DiagnosticsStore, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+use clap::{Parser, Subcommand};
+
+use crate as bevy_console;
+use crate::console::ConsoleState;
+use crate::{reply, reply_failed, ConsoleCommand, ConsoleOpen};
+
+/// Inspects values recorded in the diagnostics store
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "diag")]
+pub(crate) struct DiagCommand {
+    #[command(subcommand)]
+    action: DiagAction,
+}
+
+#[derive(Subcommand)]
+enum DiagAction {
+    /// Lists all registered diagnostics
+    List,
+    /// Prints current, average and history values of a diagnostic
+    Show {
+        /// Diagnostic path, e.g. `fps`
+        path: String,
+    },
+    /// Keeps a diagnostic pinned at the top of the console while it is open
+    Watch {
+        /// Diagnostic path, e.g. `fps`
+        path: String,
+    },
+    /// Stops watching a diagnostic, or all of them if no path is given
+    Unwatch {
+        /// Diagnostic path, e.g. `fps`
+        path: Option<String>,
+    },
+}
+
+/// Prints frames per second
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "fps")]
+pub(crate) struct FpsCommand;
+
+const DIAGNOSTICS_NOT_ENABLED: &str = "Diagnostics not enabled, add `DiagnosticsPlugin` to the app";
+
+/// Diagnostics pinned by `diag watch`
+#[derive(Resource, Default)]
+pub(crate) struct DiagWatches(Vec<String>);
+
+pub(crate) fn diag_command(
+    mut diag: ConsoleCommand<DiagCommand>,
+    store: Option<Res<DiagnosticsStore>>,
+    mut watches: ResMut<DiagWatches>,
+) {
+    let Some(Ok(DiagCommand { action })) = diag.take() else {
+        return;
+    };
+    let Some(store) = store else {
+        reply_failed!(diag, "{}", DIAGNOSTICS_NOT_ENABLED);
+        return;
+    };
+
+    match action {
+        DiagAction::List => {
+            let mut diagnostics = store.iter().collect::<Vec<_>>();
+            diagnostics.sort_by(|a, b| a.path().as_str().cmp(b.path().as_str()));
+            for diagnostic in diagnostics {
+                reply!(diag, "  {}", diagnostic_summary(diagnostic));
+            }
+        }
+        DiagAction::Show { path } => match find_diagnostic(&store, &path) {
+            Some(diagnostic) => {
+                let suffix = &diagnostic.suffix;
+                reply!(diag, "{}", diagnostic.path());
+                reply!(
+                    diag,
+                    "  current:  {}",
+                    format_value(diagnostic.value(), suffix)
+                );
+                reply!(
+                    diag,
+                    "  smoothed: {}",
+                    format_value(diagnostic.smoothed(), suffix)
+                );
+                reply!(
+                    diag,
+                    "  average:  {}",
+                    format_value(diagnostic.average(), suffix)
+                );
+                let history = diagnostic
+                    .values()
+                    .map(|value| format!("{value:.2}"))
+                    .collect::<Vec<_>>();
+                reply!(
+                    diag,
+                    "  history ({}/{}): {}",
+                    diagnostic.history_len(),
+                    diagnostic.get_max_history_length(),
+                    history.join(" ")
+                );
+            }
+            None => reply_failed!(diag, "Diagnostic '{}' does not exist", path),
+        },
+        DiagAction::Watch { path } => {
+            if find_diagnostic(&store, &path).is_none() {
+                reply_failed!(diag, "Diagnostic '{}' does not exist", path);
+                return;
+            }
+            if !watches.0.contains(&path) {
+                watches.0.push(path);
+            }
+            diag.ok();
+        }
+        DiagAction::Unwatch { path: Some(path) } => {
+            watches.0.retain(|watched| watched != &path);
+            diag.ok();
+        }
+        DiagAction::Unwatch { path: None } => {
+            watches.0.clear();
+            diag.ok();
+        }
+    }
+}
+
+pub(crate) fn fps_command(
+    mut fps: ConsoleCommand<FpsCommand>,
+    store: Option<Res<DiagnosticsStore>>,
+) {
+    if let Some(Ok(_)) = fps.take() {
+        let Some(store) = store else {
+            reply_failed!(fps, "{}", DIAGNOSTICS_NOT_ENABLED);
+            return;
+        };
+        match store.get(&FrameTimeDiagnosticsPlugin::FPS) {
+            Some(diagnostic) => reply!(fps, "{}", diagnostic_summary(diagnostic)),
+            None => reply_failed!(
+                fps,
+                "No fps diagnostic recorded, add `FrameTimeDiagnosticsPlugin` to the app"
+            ),
+        }
+    }
+}
+
+/// Refreshes the pinned lines of watched diagnostics while the console is open.
+pub(crate) fn update_diag_watches(
+    watches: Res<DiagWatches>,
+    store: Option<Res<DiagnosticsStore>>,
+    console_open: Res<ConsoleOpen>,
+    mut state: ResMut<ConsoleState>,
+) {
+    let Some(store) = store else {
+        return;
+    };
+    if !console_open.open && !watches.is_changed() {
+        return;
+    }
+
+    state.pinned_lines = watches
+        .0
+        .iter()
+        .map(|path| match find_diagnostic(&store, path) {
+            Some(diagnostic) => diagnostic_summary(diagnostic),
+            None => format!("{path}: -"),
+        })
+        .collect();
+}
+
+/// Looks up a diagnostic by its user provided path.
+///
+/// [`DiagnosticPath::new`](bevy::diagnostic::DiagnosticPath::new) asserts on malformed paths,
+/// so user input is matched against the registered paths instead.
+fn find_diagnostic<'a>(store: &'a DiagnosticsStore, path: &str) -> Option<&'a Diagnostic> {
+    store
+        .iter()
+        .find(|diagnostic| diagnostic.path().as_str() == path)
+}
+
+fn diagnostic_summary(diagnostic: &Diagnostic) -> String {
+    format!(
+        "{}: {} (avg {})",
+        diagnostic.path(),
+        format_value(diagnostic.smoothed(), &diagnostic.suffix),
+        format_value(diagnostic.average(), &diagnostic.suffix)
+    )
+}
+
+fn format_value(value: Option<f64>, suffix: &str) -> String {
+    match value {
+        Some(value) => format!("{value:.2}{suffix}"),
+        None => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    #[test]
+    fn test_watches_without_diagnostics_plugin() {
+        let mut world = World::new();
+        world.init_resource::<DiagWatches>();
+        world.init_resource::<ConsoleState>();
+        world.insert_resource(ConsoleOpen { open: true });
+
+        world.run_system_once(update_diag_watches).unwrap();
+        assert!(world.resource::<ConsoleState>().pinned_lines.is_empty());
+    }
+}
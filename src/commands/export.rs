@@ -0,0 +1,32 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use clap::Parser;
+
+use crate as bevy_console;
+use crate::commands::timestamped_path;
+use crate::console::ConsoleState;
+use crate::export::write_html;
+use crate::{reply_failed, reply_ok, ConsoleCommand, ConsoleConfiguration};
+
+/// Writes the scrollback to an HTML file, keeping colors
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "export_html")]
+pub(crate) struct ExportHtmlCommand {
+    /// File to write, defaults to `console_<unix time>.html` in the working directory
+    file: Option<PathBuf>,
+}
+
+pub(crate) fn export_html_command(
+    mut export: ConsoleCommand<ExportHtmlCommand>,
+    state: Res<ConsoleState>,
+    config: Res<ConsoleConfiguration>,
+) {
+    if let Some(Ok(ExportHtmlCommand { file })) = export.take() {
+        let path = file.unwrap_or_else(|| timestamped_path("console", "html"));
+        match write_html(&path, &state.scrollback, &config) {
+            Ok(lines) => reply_ok!(export, "Wrote {lines} lines to {}", path.display()),
+            Err(err) => reply_failed!(export, "Failed to write {}: {err}", path.display()),
+        }
+    }
+}
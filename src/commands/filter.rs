@@ -0,0 +1,125 @@
+use bevy::log::Level;
+use bevy::prelude::*;
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate as bevy_console;
+use crate::console::ConsoleState;
+use crate::scrollback::{ScrollbackFilter, LEVELS};
+use crate::ConsoleCommand;
+
+/// Shows or hides scrollback lines by level, target, kind or channel
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "filter")]
+pub(crate) struct FilterCommand {
+    #[command(subcommand)]
+    action: Option<FilterAction>,
+}
+
+#[derive(Subcommand)]
+enum FilterAction {
+    /// Shows the given kinds of lines
+    Show {
+        #[arg(required = true)]
+        kinds: Vec<LineCategory>,
+    },
+    /// Hides the given kinds of lines
+    Hide {
+        #[arg(required = true)]
+        kinds: Vec<LineCategory>,
+    },
+    /// Only shows logs whose target starts with a prefix, or all logs if omitted
+    Target { prefix: Option<String> },
+    /// Only shows lines of a channel, or all channels if omitted
+    Channel { name: Option<String> },
+    /// Shows all lines again
+    Reset,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LineCategory {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+    /// All captured logs
+    Logs,
+    /// Echoed input and command replies
+    Output,
+}
+
+pub(crate) fn filter_command(
+    mut filter: ConsoleCommand<FilterCommand>,
+    mut state: ResMut<ConsoleState>,
+) {
+    let Some(Ok(FilterCommand { action })) = filter.take() else {
+        return;
+    };
+
+    match action {
+        None => {
+            for line in describe_filter(&state.filter) {
+                filter.reply(line);
+            }
+        }
+        Some(FilterAction::Show { kinds }) => {
+            for kind in kinds {
+                set_visible(&mut state.filter, kind, true);
+            }
+            filter.ok();
+        }
+        Some(FilterAction::Hide { kinds }) => {
+            for kind in kinds {
+                set_visible(&mut state.filter, kind, false);
+            }
+            filter.ok();
+        }
+        Some(FilterAction::Target { prefix }) => {
+            state.filter.target = prefix;
+            filter.ok();
+        }
+        Some(FilterAction::Channel { name }) => {
+            state.filter.channel = name;
+            filter.ok();
+        }
+        Some(FilterAction::Reset) => {
+            state.filter = ScrollbackFilter::default();
+            filter.ok();
+        }
+    }
+}
+
+fn set_visible(filter: &mut ScrollbackFilter, kind: LineCategory, visible: bool) {
+    match kind {
+        LineCategory::Error => filter.set_level_visible(Level::ERROR, visible),
+        LineCategory::Warn => filter.set_level_visible(Level::WARN, visible),
+        LineCategory::Info => filter.set_level_visible(Level::INFO, visible),
+        LineCategory::Debug => filter.set_level_visible(Level::DEBUG, visible),
+        LineCategory::Trace => filter.set_level_visible(Level::TRACE, visible),
+        LineCategory::Logs => filter.show_logs = visible,
+        LineCategory::Output => filter.show_output = visible,
+    }
+}
+
+fn describe_filter(filter: &ScrollbackFilter) -> Vec<String> {
+    let levels = LEVELS
+        .iter()
+        .filter(|level| filter.level_visible(**level))
+        .map(|level| level.as_str().to_lowercase())
+        .collect::<Vec<_>>();
+    vec![
+        format!("output: {}", on_off(filter.show_output)),
+        format!("logs:   {}", on_off(filter.show_logs)),
+        format!("levels: {}", levels.join(" ")),
+        format!("target: {}", filter.target.as_deref().unwrap_or("*")),
+        format!("channel: {}", filter.channel.as_deref().unwrap_or("all")),
+    ]
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "shown"
+    } else {
+        "hidden"
+    }
+}
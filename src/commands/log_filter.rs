@@ -0,0 +1,53 @@
+use bevy::prelude::*;
+use clap::Parser;
+
+use crate as bevy_console;
+use crate::{reply, reply_failed, reply_ok, ConsoleCommand, ConsoleLogFilter};
+
+/// Prints or replaces the filter directives of captured logs
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "log_filter")]
+pub(crate) struct LogFilterCommand {
+    /// New directives, e.g. `mygame=debug,warn`. Prints the active ones if omitted
+    directives: Vec<String>,
+}
+
+/// Sets the captured log level of a single target
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "log_level")]
+pub(crate) struct LogLevelCommand {
+    /// Target (module path) to change, e.g. `mygame::ai`
+    target: String,
+    /// One of `off`, `error`, `warn`, `info`, `debug` or `trace`
+    level: String,
+}
+
+pub(crate) fn log_filter_command(
+    mut log_filter: ConsoleCommand<LogFilterCommand>,
+    mut filter: ResMut<ConsoleLogFilter>,
+) {
+    match log_filter.take() {
+        Some(Ok(LogFilterCommand { directives })) if directives.is_empty() => {
+            reply!(log_filter, "{}", filter.directives());
+        }
+        Some(Ok(LogFilterCommand { directives })) => {
+            match filter.set_directives(directives.join(",")) {
+                Ok(()) => log_filter.ok(),
+                Err(err) => reply_failed!(log_filter, "Invalid filter: {err}"),
+            }
+        }
+        _ => {}
+    }
+}
+
+pub(crate) fn log_level_command(
+    mut log_level: ConsoleCommand<LogLevelCommand>,
+    mut filter: ResMut<ConsoleLogFilter>,
+) {
+    if let Some(Ok(LogLevelCommand { target, level })) = log_level.take() {
+        match filter.set_level(&target, &level) {
+            Ok(()) => reply_ok!(log_level, "{}", filter.directives()),
+            Err(err) => reply_failed!(log_level, "Invalid level: {err}"),
+        }
+    }
+}
@@ -1,3 +1,21 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 pub(crate) mod clear;
+pub(crate) mod condump;
+pub(crate) mod diag;
 pub(crate) mod exit;
+pub(crate) mod export;
+pub(crate) mod filter;
 pub(crate) mod help;
+pub(crate) mod log_filter;
+pub(crate) mod time;
+
+/// Default output file of commands writing to disk, e.g. `condump_1735926008.txt`.
+pub(crate) fn timestamped_path(prefix: &str, extension: &str) -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    PathBuf::from(format!("{prefix}_{secs}.{extension}"))
+}
@@ -0,0 +1,336 @@
+use std::mem;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use clap::Parser;
+
+use crate as bevy_console;
+use crate::{reply, reply_failed, ConsoleCommand, ConsoleConfiguration, ConsoleOpen};
+
+/// Pauses virtual time
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "pause")]
+pub(crate) struct PauseCommand;
+
+/// Resumes virtual time
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "unpause")]
+pub(crate) struct UnpauseCommand;
+
+/// Prints or sets the speed of virtual time relative to real time
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "timescale")]
+pub(crate) struct TimescaleCommand {
+    /// New relative speed, e.g. 0.25 for quarter speed
+    scale: Option<f32>,
+}
+
+/// Advances paused virtual time by a number of frames
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "step")]
+pub(crate) struct StepCommand {
+    /// Number of frames to advance
+    #[arg(default_value_t = 1)]
+    frames: u32,
+    /// Count fixed timesteps instead of update frames
+    #[arg(long)]
+    fixed: bool,
+}
+
+/// Pending frame stepping requested by the `step` command.
+#[derive(Default)]
+enum PendingStep {
+    #[default]
+    None,
+    /// Number of update frames which still have to advance
+    Frames(u32),
+    /// Virtual elapsed time to advance to, and the `max_delta` to restore afterwards
+    Until {
+        elapsed: Duration,
+        max_delta: Duration,
+    },
+}
+
+const TIME_NOT_ENABLED: &str = "Time not enabled, add `TimePlugin` to the app";
+
+#[derive(Resource, Default)]
+pub(crate) struct TimeControlState {
+    step: PendingStep,
+    paused_by_console: bool,
+}
+
+impl TimeControlState {
+    /// Stops any stepping in progress, restoring the virtual clock settings it changed.
+    fn cancel_step(&mut self, time: &mut Time<Virtual>) {
+        if let PendingStep::Until { max_delta, .. } = mem::take(&mut self.step) {
+            time.set_max_delta(max_delta);
+        }
+    }
+}
+
+pub(crate) fn pause_command(
+    mut pause: ConsoleCommand<PauseCommand>,
+    time: Option<ResMut<Time<Virtual>>>,
+    mut state: ResMut<TimeControlState>,
+) {
+    if let Some(Ok(_)) = pause.take() {
+        let Some(mut time) = time else {
+            reply_failed!(pause, "{}", TIME_NOT_ENABLED);
+            return;
+        };
+        state.cancel_step(&mut time);
+        state.paused_by_console = false;
+        time.pause();
+        pause.ok();
+    }
+}
+
+pub(crate) fn unpause_command(
+    mut unpause: ConsoleCommand<UnpauseCommand>,
+    time: Option<ResMut<Time<Virtual>>>,
+    mut state: ResMut<TimeControlState>,
+) {
+    if let Some(Ok(_)) = unpause.take() {
+        let Some(mut time) = time else {
+            reply_failed!(unpause, "{}", TIME_NOT_ENABLED);
+            return;
+        };
+        state.cancel_step(&mut time);
+        state.paused_by_console = false;
+        time.unpause();
+        unpause.ok();
+    }
+}
+
+pub(crate) fn timescale_command(
+    mut timescale: ConsoleCommand<TimescaleCommand>,
+    time: Option<ResMut<Time<Virtual>>>,
+) {
+    let Some(Ok(TimescaleCommand { scale })) = timescale.take() else {
+        return;
+    };
+    let Some(mut time) = time else {
+        reply_failed!(timescale, "{}", TIME_NOT_ENABLED);
+        return;
+    };
+    match scale {
+        Some(scale) => {
+            if !scale.is_finite() || scale < 0.0 {
+                reply_failed!(
+                    timescale,
+                    "Timescale must be a non-negative number, got {scale}"
+                );
+                return;
+            }
+            time.set_relative_speed(scale);
+            timescale.ok();
+        }
+        None => {
+            reply!(timescale, "{}", time.relative_speed());
+        }
+    }
+}
+
+pub(crate) fn step_command(
+    mut step: ConsoleCommand<StepCommand>,
+    time: Option<ResMut<Time<Virtual>>>,
+    fixed: Option<Res<Time<Fixed>>>,
+    mut state: ResMut<TimeControlState>,
+) {
+    if let Some(Ok(StepCommand {
+        frames,
+        fixed: use_fixed,
+    })) = step.take()
+    {
+        let (Some(mut time), Some(fixed)) = (time, fixed) else {
+            reply_failed!(step, "{}", TIME_NOT_ENABLED);
+            return;
+        };
+        state.cancel_step(&mut time);
+        if frames == 0 {
+            step.ok();
+            return;
+        }
+
+        state.step = if use_fixed {
+            // Land exactly on the n-th fixed timestep, accounting for time already accumulated
+            PendingStep::Until {
+                elapsed: time.elapsed() + fixed.timestep() * frames - fixed.overstep(),
+                max_delta: time.max_delta(),
+            }
+        } else {
+            PendingStep::Frames(frames)
+        };
+        time.pause();
+        step.ok();
+    }
+}
+
+/// Unpauses virtual time for exactly the frames requested by the `step` command.
+///
+/// Runs in [`First`] before [`TimeSystem`](bevy::time::TimeSystem) so the decision applies to
+/// the frame that is about to start.
+pub(crate) fn advance_time_step(
+    time: Option<ResMut<Time<Virtual>>>,
+    mut state: ResMut<TimeControlState>,
+) {
+    let Some(mut time) = time else {
+        return;
+    };
+    match &mut state.step {
+        PendingStep::None => {}
+        PendingStep::Frames(0) => {
+            state.step = PendingStep::None;
+            time.pause();
+        }
+        PendingStep::Frames(frames) => {
+            *frames -= 1;
+            time.unpause();
+        }
+        PendingStep::Until { elapsed, .. } => {
+            let remaining = elapsed.saturating_sub(time.elapsed());
+            if remaining.is_zero() {
+                state.cancel_step(&mut time);
+                time.pause();
+            } else {
+                time.unpause();
+                // Clamping the delta guarantees the clock never overshoots the target
+                time.set_max_delta(remaining);
+            }
+        }
+    }
+}
+
+/// Pauses virtual time while the console is open, if enabled in [`ConsoleConfiguration`].
+pub(crate) fn pause_time_while_open(
+    config: Res<ConsoleConfiguration>,
+    console_open: Res<ConsoleOpen>,
+    time: Option<ResMut<Time<Virtual>>>,
+    mut state: ResMut<TimeControlState>,
+) {
+    let Some(mut time) = time else {
+        return;
+    };
+    if !config.pause_time_while_open || !console_open.is_changed() {
+        return;
+    }
+
+    if console_open.open {
+        if !time.is_paused() {
+            time.pause();
+            state.paused_by_console = true;
+        }
+    } else if state.paused_by_console {
+        // Only resume if nothing else paused or stepped time in the meantime
+        state.paused_by_console = false;
+        if matches!(state.step, PendingStep::None) {
+            time.unpause();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    fn world(step: PendingStep) -> World {
+        let mut world = World::new();
+        let mut time = Time::<Virtual>::default();
+        time.pause();
+        world.insert_resource(time);
+        world.insert_resource(TimeControlState {
+            step,
+            paused_by_console: false,
+        });
+        world
+    }
+
+    fn advance(world: &mut World) -> bool {
+        world.run_system_once(advance_time_step).unwrap();
+        world.resource::<Time<Virtual>>().is_paused()
+    }
+
+    #[test]
+    fn test_systems_without_time_plugin() {
+        let mut world = World::new();
+        world.init_resource::<TimeControlState>();
+        world.init_resource::<ConsoleConfiguration>();
+        world.insert_resource(ConsoleOpen { open: true });
+
+        world.run_system_once(advance_time_step).unwrap();
+        world.run_system_once(pause_time_while_open).unwrap();
+    }
+
+    #[test]
+    fn test_step_frames() {
+        let mut world = world(PendingStep::Frames(2));
+
+        assert!(!advance(&mut world));
+        assert!(!advance(&mut world));
+        assert!(advance(&mut world));
+        assert!(matches!(
+            world.resource::<TimeControlState>().step,
+            PendingStep::None
+        ));
+        assert!(advance(&mut world));
+    }
+
+    #[test]
+    fn test_step_until_fixed_timestep() {
+        let max_delta = Duration::from_millis(250);
+        let mut world = world(PendingStep::Until {
+            elapsed: Duration::from_millis(100),
+            max_delta,
+        });
+        world
+            .resource_mut::<Time<Virtual>>()
+            .advance_by(Duration::from_millis(40));
+
+        assert!(!advance(&mut world));
+        assert_eq!(
+            world.resource::<Time<Virtual>>().max_delta(),
+            Duration::from_millis(60)
+        );
+
+        world
+            .resource_mut::<Time<Virtual>>()
+            .advance_by(Duration::from_millis(60));
+        assert!(advance(&mut world));
+        assert_eq!(world.resource::<Time<Virtual>>().max_delta(), max_delta);
+    }
+
+    #[test]
+    fn test_pause_while_open() {
+        let mut world = world(PendingStep::None);
+        world.resource_mut::<Time<Virtual>>().unpause();
+        world.insert_resource(ConsoleConfiguration {
+            pause_time_while_open: true,
+            ..default()
+        });
+        world.insert_resource(ConsoleOpen { open: true });
+
+        world.run_system_once(pause_time_while_open).unwrap();
+        assert!(world.resource::<Time<Virtual>>().is_paused());
+
+        world.resource_mut::<ConsoleOpen>().open = false;
+        world.run_system_once(pause_time_while_open).unwrap();
+        assert!(!world.resource::<Time<Virtual>>().is_paused());
+    }
+
+    #[test]
+    fn test_closing_keeps_time_paused_by_user() {
+        let mut world = world(PendingStep::None);
+        world.insert_resource(ConsoleConfiguration {
+            pause_time_while_open: true,
+            ..default()
+        });
+        world.insert_resource(ConsoleOpen { open: true });
+
+        world.run_system_once(pause_time_while_open).unwrap();
+        world.resource_mut::<ConsoleOpen>().open = false;
+        world.run_system_once(pause_time_while_open).unwrap();
+        assert!(world.resource::<Time<Virtual>>().is_paused());
+    }
+}
@@ -1,12 +1,15 @@
+use bevy::diagnostic::FrameCount;
 use bevy::ecs::resource::Resource;
 use bevy::ecs::{
     component::Tick,
+    event::EventId,
     system::{ScheduleSystem, SystemMeta, SystemParam},
     world::unsafe_world_cell::UnsafeWorldCell,
 };
 use bevy::platform::hash::FixedState;
 use bevy::{input::keyboard::KeyboardInput, prelude::*};
-use bevy_egui::egui::{self, Align, ScrollArea, TextEdit};
+use bevy_egui::egui::text_selection::{visuals::paint_text_selection, CursorRange};
+use bevy_egui::egui::{self, pos2, vec2, Pos2, Rect, ScrollArea, Sense, TextEdit};
 use bevy_egui::egui::{text::LayoutJob, text_selection::CCursorRange};
 use bevy_egui::egui::{Context, Id};
 use bevy_egui::{
@@ -16,21 +19,40 @@ use bevy_egui::{
 use clap::{CommandFactory, FromArgMatches};
 use core::str;
 use shlex::Shlex;
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::hash::BuildHasher;
 use std::marker::PhantomData;
 use std::mem;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use trie_rs::Trie;
 
 use crate::{
-    color::{parse_ansi_styled_str, TextFormattingOverride},
-    ConsoleSet,
+    color::{parse_ansi_styled_str, TextFormattingOverride, DEFAULT_ANSI_COLORS},
+    link::{find_links, link_at, style_links, ConsoleLink, ConsoleLinkClicked},
+    pipe::Pipeline,
+    scrollback::{
+        BlockHeader, BlockStatus, LineKind, LineStamp, Scrollback, ScrollbackFilter,
+        ScrollbackView, COMMANDS_CHANNEL, DEFAULT_SCROLLBACK_SIZE, LEVELS,
+    },
+    search::ScrollbackSearch,
+    selection::{CopyAction, ScrollbackSelection, TextPoint},
+    session::{save_session, ConsoleSession},
+    task::{CommandTask, CommandTaskStarted},
+    ConsoleSet, LogRecord,
 };
 
 type ConsoleCommandEnteredReaderSystemParam = EventReader<'static, 'static, ConsoleCommandEntered>;
 
 type PrintConsoleLineWriterSystemParam = EventWriter<'static, PrintConsoleLine>;
 
+type ReplyTimesSystemParam = ResMut<'static, ReplyTimes>;
+
+type UpdateConsoleLineWriterSystemParam = EventWriter<'static, UpdateConsoleLine>;
+
+type CommandTaskStartedWriterSystemParam = EventWriter<'static, CommandTaskStarted>;
+
 /// A super-trait for command like structures
 pub trait Command: NamedCommand + CommandFactory + FromArgMatches + Sized + Resource {}
 impl<T: NamedCommand + CommandFactory + FromArgMatches + Sized + Resource> Command for T {}
@@ -69,7 +91,11 @@ pub trait NamedCommand {
 /// ```
 pub struct ConsoleCommand<'w, T> {
     command: Option<Result<T, clap::Error>>,
+    invocation: Option<InvocationId>,
     console_line: EventWriter<'w, PrintConsoleLine>,
+    reply_times: ResMut<'w, ReplyTimes>,
+    update_line: EventWriter<'w, UpdateConsoleLine>,
+    task_started: EventWriter<'w, CommandTaskStarted>,
 }
 
 impl<T> ConsoleCommand<'_, T> {
@@ -81,30 +107,35 @@ impl<T> ConsoleCommand<'_, T> {
         mem::take(&mut self.command)
     }
 
+    /// Returns the invocation of the command, which replies are attributed to.
+    ///
+    /// `None` if the command was not executed.
+    pub fn invocation(&self) -> Option<InvocationId> {
+        self.invocation
+    }
+
     /// Print `[ok]` in the console.
     pub fn ok(&mut self) {
-        self.console_line
-            .write(PrintConsoleLine::new("[ok]".into()));
+        self.print("[ok]".into());
     }
 
     /// Print `[failed]` in the console.
     pub fn failed(&mut self) {
-        self.console_line
-            .write(PrintConsoleLine::new("[failed]".into()));
+        self.print("[failed]".into());
     }
 
     /// Print a reply in the console.
     ///
     /// See [`reply!`](crate::reply) for usage with the [`format!`] syntax.
     pub fn reply(&mut self, msg: impl Into<String>) {
-        self.console_line.write(PrintConsoleLine::new(msg.into()));
+        self.print(msg.into());
     }
 
     /// Print a reply in the console followed by `[ok]`.
     ///
     /// See [`reply_ok!`](crate::reply_ok) for usage with the [`format!`] syntax.
     pub fn reply_ok(&mut self, msg: impl Into<String>) {
-        self.console_line.write(PrintConsoleLine::new(msg.into()));
+        self.print(msg.into());
         self.ok();
     }
 
@@ -112,15 +143,65 @@ impl<T> ConsoleCommand<'_, T> {
     ///
     /// See [`reply_failed!`](crate::reply_failed) for usage with the [`format!`] syntax.
     pub fn reply_failed(&mut self, msg: impl Into<String>) {
-        self.console_line.write(PrintConsoleLine::new(msg.into()));
+        self.print(msg.into());
         self.failed();
     }
+
+    /// Print a reply in the console which can be changed later on.
+    ///
+    /// The returned handle can be passed to [`update_line`](Self::update_line) and
+    /// [`remove_line`](Self::remove_line) in later frames, e.g. to show the progress of a long
+    /// running command in a single line.
+    pub fn reply_line(&mut self, msg: impl Into<String>) -> LineHandle {
+        let handle = LineHandle::next();
+        let mut line = PrintConsoleLine::new(msg.into()).with_handle(handle);
+        line.invocation = self.invocation;
+        self.write(line);
+        handle
+    }
+
+    /// Replace the text of a line printed with [`reply_line`](Self::reply_line).
+    pub fn update_line(&mut self, handle: LineHandle, msg: impl Into<String>) {
+        self.update_line
+            .write(UpdateConsoleLine::replace(handle, msg.into()));
+    }
+
+    /// Remove a line printed with [`reply_line`](Self::reply_line) from the console.
+    pub fn remove_line(&mut self, handle: LineHandle) {
+        self.update_line.write(UpdateConsoleLine::remove(handle));
+    }
+
+    fn print(&mut self, line: String) {
+        let mut line = PrintConsoleLine::new(line);
+        line.invocation = self.invocation;
+        self.write(line);
+    }
+
+    fn write(&mut self, line: PrintConsoleLine) {
+        let id = self.console_line.write(line);
+        self.reply_times.0.insert(id, SystemTime::now());
+    }
+}
+
+impl<T: NamedCommand> ConsoleCommand<'_, T> {
+    /// Keeps the command running after this frame, until the returned task is finished.
+    ///
+    /// Command systems only run in frames where a command was entered, so the task has to be
+    /// moved to an async block or another system to make progress. See [`CommandTask`].
+    pub fn start_task(&mut self) -> CommandTask {
+        let task = CommandTask::new(T::name(), self.invocation);
+        self.task_started.write(CommandTaskStarted(task.clone()));
+        task
+    }
 }
 
 pub struct ConsoleCommandState<T> {
     #[allow(clippy::type_complexity)]
     event_reader: <ConsoleCommandEnteredReaderSystemParam as SystemParam>::State,
     console_line: <PrintConsoleLineWriterSystemParam as SystemParam>::State,
+    reply_times: <ReplyTimesSystemParam as SystemParam>::State,
+    update_line: <UpdateConsoleLineWriterSystemParam as SystemParam>::State,
+    task_started: <CommandTaskStartedWriterSystemParam as SystemParam>::State,
     marker: PhantomData<T>,
 }
 
@@ -131,9 +212,15 @@ unsafe impl<T: Command> SystemParam for ConsoleCommand<'_, T> {
     fn init_state(world: &mut World, system_meta: &mut SystemMeta) -> Self::State {
         let event_reader = ConsoleCommandEnteredReaderSystemParam::init_state(world, system_meta);
         let console_line = PrintConsoleLineWriterSystemParam::init_state(world, system_meta);
+        let reply_times = ReplyTimesSystemParam::init_state(world, system_meta);
+        let update_line = UpdateConsoleLineWriterSystemParam::init_state(world, system_meta);
+        let task_started = CommandTaskStartedWriterSystemParam::init_state(world, system_meta);
         ConsoleCommandState {
             event_reader,
             console_line,
+            reply_times,
+            update_line,
+            task_started,
             marker: PhantomData,
         }
     }
@@ -157,9 +244,29 @@ unsafe impl<T: Command> SystemParam for ConsoleCommand<'_, T> {
             world,
             change_tick,
         );
+        let mut reply_times = ReplyTimesSystemParam::get_param(
+            &mut state.reply_times,
+            system_meta,
+            world,
+            change_tick,
+        );
+        let update_line = UpdateConsoleLineWriterSystemParam::get_param(
+            &mut state.update_line,
+            system_meta,
+            world,
+            change_tick,
+        );
+        let task_started = CommandTaskStartedWriterSystemParam::get_param(
+            &mut state.task_started,
+            system_meta,
+            world,
+            change_tick,
+        );
 
+        let mut invocation = None;
         let command = event_reader.read().find_map(|command| {
             if T::name() == command.command_name {
+                invocation = Some(command.invocation);
                 let clap_command = T::command().no_binary_name(true);
                 // .color(clap::ColorChoice::Always);
                 let arg_matches = clap_command.try_get_matches_from(command.args.iter());
@@ -174,7 +281,15 @@ unsafe impl<T: Command> SystemParam for ConsoleCommand<'_, T> {
                         return Some(T::from_arg_matches(&matches));
                     }
                     Err(err) => {
-                        console_line.write(PrintConsoleLine::new(err.to_string()));
+                        // Help and version are shown through errors as well
+                        let status = if err.use_stderr() { "[failed]" } else { "[ok]" };
+                        let ids = console_line.write_batch(
+                            [err.to_string(), status.to_string()].map(|line| {
+                                PrintConsoleLine::new(line).with_invocation(command.invocation)
+                            }),
+                        );
+                        let now = SystemTime::now();
+                        reply_times.0.extend(ids.map(|id| (id, now)));
                         return Some(Err(err));
                     }
                 }
@@ -184,10 +299,27 @@ unsafe impl<T: Command> SystemParam for ConsoleCommand<'_, T> {
 
         ConsoleCommand {
             command,
+            invocation,
             console_line,
+            reply_times,
+            update_line,
+            task_started,
         }
     }
 }
+
+/// Identifies one entered command, so its output can be told apart from other lines.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct InvocationId(u64);
+
+impl InvocationId {
+    /// Returns a new unique id.
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
 /// Parsed raw console command into `command` and `args`.
 #[derive(Clone, Debug, Event)]
 pub struct ConsoleCommandEntered {
@@ -195,6 +327,19 @@ pub struct ConsoleCommandEntered {
     pub command_name: String,
     /// Raw parsed arguments
     pub args: Vec<String>,
+    /// Id replies of the command are attributed to
+    pub invocation: InvocationId,
+}
+
+impl ConsoleCommandEntered {
+    /// Creates a command to run as if it was entered in the console, with a new invocation id.
+    pub fn new(command_name: impl Into<String>, args: Vec<String>) -> Self {
+        Self {
+            command_name: command_name.into(),
+            args,
+            invocation: InvocationId::next(),
+        }
+    }
 }
 
 /// Events to print to the console.
@@ -202,12 +347,124 @@ pub struct ConsoleCommandEntered {
 pub struct PrintConsoleLine {
     /// Console line
     pub line: String,
+    /// Command invocation which printed the line, if any
+    pub invocation: Option<InvocationId>,
+    /// Handle to change the line later on with [`UpdateConsoleLine`], set with
+    /// [`with_handle`](Self::with_handle)
+    ///
+    /// Lines captured by a pipe or redirect lose their handle.
+    pub handle: Option<LineHandle>,
+    /// Channel to print the line to, like `"network"`, instead of the `commands` channel
+    ///
+    /// Each channel gets a tab in the console, next to the `all` tab. Captured logs are shown in
+    /// the `logs` channel.
+    pub channel: Option<String>,
 }
 
 impl PrintConsoleLine {
     /// Creates a new console line to print.
     pub const fn new(line: String) -> Self {
-        Self { line }
+        Self {
+            line,
+            invocation: None,
+            handle: None,
+            channel: None,
+        }
+    }
+
+    /// Attributes the line to a command, so it is shown in the block of the command and goes
+    /// through its pipes and redirects.
+    pub const fn with_invocation(mut self, invocation: InvocationId) -> Self {
+        self.invocation = Some(invocation);
+        self
+    }
+
+    /// Prints the line with a handle, so it can be replaced or removed later on.
+    pub const fn with_handle(mut self, handle: LineHandle) -> Self {
+        self.handle = Some(handle);
+        self
+    }
+}
+
+/// When the replies of commands were written, to show them in order with the logs captured in
+/// the same frame
+#[derive(Default, Resource)]
+pub(crate) struct ReplyTimes(HashMap<EventId<PrintConsoleLine>, SystemTime>);
+
+/// Identifies a printed line, so it can be replaced or removed later on.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct LineHandle(u64);
+
+impl LineHandle {
+    /// Returns a new unique handle.
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Change to a line printed with a [`LineHandle`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LineUpdate {
+    /// Replace the text of the line
+    Replace(String),
+    /// Remove the line from the console
+    Remove,
+}
+
+/// Events to change a line printed with [`PrintConsoleLine::with_handle`].
+///
+/// Updates are applied after the lines printed in the same frame. Updates to lines which were
+/// evicted or cleared from the scrollback are ignored.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_console::{LineHandle, PrintConsoleLine, UpdateConsoleLine};
+/// fn show_progress(
+///     mut handle: Local<Option<LineHandle>>,
+///     mut progress: Local<u32>,
+///     mut print: EventWriter<PrintConsoleLine>,
+///     mut update: EventWriter<UpdateConsoleLine>,
+/// ) {
+///     *progress = (*progress + 1).min(100);
+///     let line = format!("Loading: {}%", *progress);
+///     match *handle {
+///         Some(handle) => {
+///             update.write(UpdateConsoleLine::replace(handle, line));
+///         }
+///         None => {
+///             let new_handle = LineHandle::next();
+///             print.write(PrintConsoleLine::new(line).with_handle(new_handle));
+///             *handle = Some(new_handle);
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Eq, Event, PartialEq)]
+pub struct UpdateConsoleLine {
+    /// Line to change
+    pub handle: LineHandle,
+    /// Change to apply
+    pub update: LineUpdate,
+}
+
+impl UpdateConsoleLine {
+    /// Replaces the text of the line.
+    pub const fn replace(handle: LineHandle, line: String) -> Self {
+        Self {
+            handle,
+            update: LineUpdate::Replace(line),
+        }
+    }
+
+    /// Removes the line from the console.
+    pub const fn remove(handle: LineHandle) -> Self {
+        Self {
+            handle,
+            update: LineUpdate::Remove,
+        }
     }
 }
 
@@ -240,15 +497,38 @@ pub struct ConsoleConfiguration {
     pub moveable: bool,
     /// show the title bar or not
     pub show_title_bar: bool,
+    /// Show the console in its own window when open, disable to only show it with a
+    /// [`ConsoleWidget`]
+    pub show_window: bool,
+    /// How the console window is shown
+    pub presentation: ConsolePresentation,
+    /// File keeping the window position and size, open state, selected tab and filter between
+    /// runs, restored on startup
+    pub session_path: Option<PathBuf>,
     /// Background color of console window  
     pub background_color: Color32,
     /// Foreground (text) color
     pub foreground_color: Color32,
+    /// Colors of the 8 standard and 8 bright ANSI colors, which also start the 256 color palette
+    pub ansi_colors: [Color32; 16],
     /// Number of suggested commands to show
     pub num_suggestions: usize,
     /// Custom completion sequences,
     /// for example [vec!["custom", "foo"]], will complete `custom foo` when typing `custom`
     pub arg_completions: Vec<Vec<String>>,
+    /// Pause virtual time while the console is open
+    pub pause_time_while_open: bool,
+    /// Merge consecutive identical lines into one entry with a repeat counter, off by default
+    pub collapse_repeated_lines: bool,
+    /// Maximum number of scrollback entries, the oldest ones are evicted first. A size of zero is
+    /// raised to one.
+    pub scrollback_size: usize,
+    /// File receiving lines evicted from the scrollback, without ANSI styling
+    pub scrollback_spill_path: Option<PathBuf>,
+    /// Record when each scrollback entry arrived: wall-clock time, [`Time`] elapsed and frame
+    pub record_line_stamps: bool,
+    /// Show the recorded arrival of each entry in a dimmed column, can be toggled in the console
+    pub show_line_stamps: bool,
 }
 
 #[derive(Resource, Default)]
@@ -259,6 +539,10 @@ pub struct ConsoleCache {
     pub(crate) predictions_hash_key: Option<u64>,
     pub(crate) predictions_cache: Vec<String>,
     pub(crate) prediction_matches_buffer: bool,
+    /// Rows of the filtered scrollback
+    pub(crate) scrollback_view: ScrollbackView,
+    /// Scale factor the cached scrollback layouts were created with
+    pub(crate) layout_pixels_per_point: f32,
 }
 
 impl Default for ConsoleConfiguration {
@@ -277,10 +561,20 @@ impl Default for ConsoleConfiguration {
             resizable: true,
             moveable: true,
             show_title_bar: true,
+            show_window: true,
+            presentation: ConsolePresentation::Window,
+            session_path: None,
             background_color: Color32::from_black_alpha(102),
             foreground_color: Color32::LIGHT_GRAY,
+            ansi_colors: DEFAULT_ANSI_COLORS,
             num_suggestions: 4,
             arg_completions: Default::default(),
+            pause_time_while_open: false,
+            collapse_repeated_lines: false,
+            scrollback_size: DEFAULT_SCROLLBACK_SIZE,
+            scrollback_spill_path: None,
+            record_line_stamps: false,
+            show_line_stamps: true,
         }
     }
 }
@@ -302,9 +596,19 @@ impl Clone for ConsoleConfiguration {
             resizable: true,
             moveable: true,
             show_title_bar: true,
+            show_window: self.show_window,
+            presentation: self.presentation,
+            session_path: self.session_path.clone(),
             background_color: Color32::from_black_alpha(102),
             foreground_color: Color32::LIGHT_GRAY,
+            ansi_colors: self.ansi_colors,
             num_suggestions: 4,
+            pause_time_while_open: self.pause_time_while_open,
+            collapse_repeated_lines: self.collapse_repeated_lines,
+            scrollback_size: self.scrollback_size,
+            scrollback_spill_path: self.scrollback_spill_path.clone(),
+            record_line_stamps: self.record_line_stamps,
+            show_line_stamps: self.show_line_stamps,
         }
     }
 }
@@ -360,6 +664,21 @@ impl AddConsoleCommand for App {
     }
 }
 
+/// How the console window is shown
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ConsolePresentation {
+    /// Floating window placed with `left_pos`, `top_pos`, `width` and `height`
+    #[default]
+    Window,
+    /// Quake-style console sliding down from the top, spanning the whole width of the window
+    DropDown {
+        /// Height as a fraction of the window height, from `0.0` to `1.0`
+        height: f32,
+        /// Seconds it takes to slide in or out
+        slide_time: f32,
+    },
+}
+
 /// Console open state
 #[derive(Default, Resource)]
 pub struct ConsoleOpen {
@@ -370,31 +689,63 @@ pub struct ConsoleOpen {
 #[derive(Resource)]
 pub(crate) struct ConsoleState {
     pub(crate) buf: String,
-    pub(crate) scrollback: Vec<String>,
+    pub(crate) scrollback: Scrollback,
+    pub(crate) filter: ScrollbackFilter,
+    pub(crate) search: ScrollbackSearch,
     pub(crate) history: VecDeque<String>,
     pub(crate) history_index: usize,
     pub(crate) suggestion_index: Option<usize>,
+    /// Lines shown above the scrollback, refreshed every frame by their owner
+    pub(crate) pinned_lines: Vec<String>,
+    /// Commands entered with `|`, `>` or `>>`, whose output is still being captured
+    pub(crate) pipelines: BTreeMap<InvocationId, Pipeline>,
+    /// Scrollback ids of the lines printed with a handle
+    pub(crate) line_handles: HashMap<LineHandle, u64>,
+    /// Commands still running after the frame they were entered in
+    pub(crate) tasks: Vec<CommandTask>,
+    pub(crate) selection: ScrollbackSelection,
+    /// Show the arrival of each entry, follows [`ConsoleConfiguration::show_line_stamps`]
+    pub(crate) show_line_stamps: bool,
+    /// Top left corner of the window, once moved or restored
+    pub(crate) window_pos: Option<Pos2>,
+    /// Size of the window contents, once resized or restored
+    pub(crate) window_size: Option<egui::Vec2>,
+    /// Last session written to [`ConsoleConfiguration::session_path`]
+    pub(crate) saved_session: Option<ConsoleSession>,
 }
 
 impl Default for ConsoleState {
     fn default() -> Self {
         ConsoleState {
             buf: String::default(),
-            scrollback: Vec::new(),
+            scrollback: Scrollback::default(),
+            filter: ScrollbackFilter::default(),
+            search: ScrollbackSearch::default(),
             history: VecDeque::from([String::new()]),
             history_index: 0,
             suggestion_index: None,
+            pinned_lines: Vec::new(),
+            pipelines: BTreeMap::new(),
+            line_handles: HashMap::new(),
+            tasks: Vec::new(),
+            selection: ScrollbackSelection::default(),
+            show_line_stamps: true,
+            window_pos: None,
+            window_size: None,
+            saved_session: None,
         }
     }
 }
 
+const FONT_SIZE: f32 = 14.0;
+
 fn default_style(config: &ConsoleConfiguration) -> TextFormat {
-    TextFormat::simple(FontId::monospace(14f32), config.foreground_color)
+    TextFormat::simple(FontId::monospace(FONT_SIZE), config.foreground_color)
 }
 
 fn style_ansi_text(str: &str, config: &ConsoleConfiguration) -> LayoutJob {
     let mut layout_job = LayoutJob::default();
-    for (str, overrides) in parse_ansi_styled_str(str).into_iter() {
+    for (str, overrides) in parse_ansi_styled_str(str, &config.ansi_colors) {
         let mut current_style = default_style(config);
 
         for o in overrides {
@@ -475,14 +826,291 @@ pub(crate) fn recompute_predictions(
     }
 }
 
+/// The console, shown in any [`egui::Ui`]
+///
+/// The built-in console window shows this widget. Use it directly to embed the console in your
+/// own panels, and set [`ConsoleConfiguration::show_window`] to `false` to hide the built-in
+/// window. Systems showing it should run in the [`EguiContextPass`](bevy_egui::EguiContextPass)
+/// schedule, in [`ConsoleSet::ConsoleUI`] like the built-in window.
+///
+/// # Example
+///
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_console::{ConsoleConfiguration, ConsolePlugin, ConsoleSet, ConsoleWidget};
+/// # use bevy_egui::{egui, EguiContextPass, EguiContexts};
+/// App::new()
+///     .add_plugins((DefaultPlugins, ConsolePlugin))
+///     .insert_resource(ConsoleConfiguration {
+///         show_window: false,
+///         ..default()
+///     })
+///     .add_systems(EguiContextPass, console_panel.in_set(ConsoleSet::ConsoleUI));
+///
+/// fn console_panel(mut contexts: EguiContexts, mut console: ConsoleWidget) {
+///     let Some(ctx) = contexts.try_ctx_mut() else {
+///         return;
+///     };
+///     egui::TopBottomPanel::bottom("console")
+///         .resizable(true)
+///         .show(ctx, |ui| console.show(ui));
+/// }
+/// ```
+#[derive(SystemParam)]
+pub struct ConsoleWidget<'w> {
+    config: Res<'w, ConsoleConfiguration>,
+    cache: ResMut<'w, ConsoleCache>,
+    state: ResMut<'w, ConsoleState>,
+    command_entered: EventWriter<'w, ConsoleCommandEntered>,
+    link_clicked: EventWriter<'w, ConsoleLinkClicked>,
+    console_open: ResMut<'w, ConsoleOpen>,
+}
+
+impl ConsoleWidget<'_> {
+    /// Shows the scrollback, the toolbars, the input line and its suggestions, filling `ui`.
+    ///
+    /// The input takes the keyboard focus while the console is [open](ConsoleOpen).
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        let config = &*self.config;
+        let cache = &mut *self.cache;
+        let state = &mut *self.state;
+        let command_entered = &mut self.command_entered;
+        let link_clicked = &mut self.link_clicked;
+        let console_open = &*self.console_open;
+        let scroll_to_bottom = self.console_open.is_changed();
+
+        // Cached layouts depend on the configured colors and the scale factor
+        let pixels_per_point = ui.ctx().pixels_per_point();
+        if self.config.is_changed() || cache.layout_pixels_per_point != pixels_per_point {
+            state.scrollback.invalidate_layouts();
+            cache.layout_pixels_per_point = pixels_per_point;
+        }
+        if self.config.is_changed() {
+            state.show_line_stamps = config.show_line_stamps;
+        }
+
+        // Recompute predictions if the buffer changed
+        recompute_predictions(state, cache, config.num_suggestions);
+
+        ui.vertical(|ui| {
+            ui.style_mut().visuals.extreme_bg_color = config.background_color;
+            ui.style_mut().visuals.override_text_color = Some(config.foreground_color);
+
+            // Pinned lines
+            if !state.pinned_lines.is_empty() {
+                for line in &state.pinned_lines {
+                    ui.label(style_ansi_text(line, config));
+                }
+                ui.separator();
+            }
+
+            // Channel tabs, once lines were printed to more than one channel
+            if state.scrollback.channels().len() > 1 || state.filter.channel.is_some() {
+                let ConsoleState {
+                    scrollback, filter, ..
+                } = &mut *state;
+                channel_tabs(ui, scrollback.channels(), &mut filter.channel);
+                ui.separator();
+            }
+
+            // Level and kind toggles, only useful once logs are captured
+            let has_logs = state.scrollback.has_logs();
+            if has_logs || config.record_line_stamps {
+                let ConsoleState {
+                    filter,
+                    show_line_stamps,
+                    ..
+                } = &mut *state;
+                filter_toolbar(
+                    ui,
+                    has_logs.then_some(filter),
+                    config.record_line_stamps.then_some(show_line_stamps),
+                );
+                ui.separator();
+            }
+
+            // Search bar
+            let open_search = ui.input(|i| i.modifiers.ctrl & i.key_pressed(egui::Key::F));
+            if open_search {
+                state.search.open = true;
+            }
+            let jump = if state.search.open {
+                let jump = search_bar(ui, &mut state.search, open_search);
+                ui.separator();
+                jump
+            } else {
+                None
+            };
+
+            let modified_from = state.scrollback.take_modified();
+            let view = &mut cache.scrollback_view;
+            view.update(&state.scrollback, &state.filter, modified_from);
+            let ConsoleState {
+                scrollback, search, ..
+            } = &mut *state;
+            if search.update(scrollback, view, modified_from) {
+                scrollback.invalidate_layouts();
+            }
+            let mut scroll_to_row = None;
+            if let Some(id) = jump.and_then(|backwards| search.jump(backwards)) {
+                scrollback.invalidate_layouts();
+                scroll_to_row = view.row_of(id);
+            }
+
+            const WRITE_AREA_HEIGHT: f32 = 30.0;
+            let mut write_area_height = WRITE_AREA_HEIGHT;
+            if !state.tasks.is_empty() {
+                write_area_height +=
+                    ui.text_style_height(&egui::TextStyle::Monospace) + ui.spacing().item_spacing.y;
+            }
+            let scroll_height = ui.available_height() - write_area_height;
+            // Scroll area
+            let clicked_link = scrollback_area(
+                ui,
+                state,
+                &cache.scrollback_view,
+                config,
+                scroll_height,
+                scroll_to_bottom,
+                scroll_to_row,
+            );
+            if let Some(link) = clicked_link {
+                link_clicked.write(ConsoleLinkClicked { link });
+            }
+
+            // Separator
+            ui.separator();
+
+            // Copy the selection on ctrl+c, otherwise clear the line, or cancel the
+            // running commands if it is empty
+            if ui.input(|i| i.modifiers.ctrl & i.key_pressed(egui::Key::C)) {
+                if !state.selection.is_empty() {
+                    let text = CopyAction::Selection.text(
+                        &state.scrollback,
+                        &cache.scrollback_view,
+                        &state.selection,
+                    );
+                    ui.ctx().copy_text(text.unwrap_or_default());
+                    state.selection.clear();
+                } else if state.buf.is_empty() {
+                    cancel_tasks(state);
+                } else {
+                    state.buf.clear();
+                }
+                return;
+            }
+
+            if !state.tasks.is_empty() {
+                running_tasks_label(ui, &state.tasks);
+            }
+
+            // Clear history on ctrl+l
+            if ui.input(|i| i.modifiers.ctrl & i.key_pressed(egui::Key::L)) {
+                state.scrollback.clear();
+                return;
+            }
+
+            // Input
+            let text_edit = TextEdit::singleline(&mut state.buf)
+                .desired_width(f32::INFINITY)
+                .lock_focus(true)
+                .font(egui::TextStyle::Monospace);
+
+            let text_edit_response = ui.add(text_edit);
+
+            // show a few suggestions
+            if text_edit_response.has_focus()
+                && !state.buf.is_empty()
+                && !cache.prediction_matches_buffer
+            {
+                // create the area to show suggestions
+                let suggestions_area = egui::Area::new(ui.auto_id_with("suggestions"))
+                    .fixed_pos(ui.next_widget_position())
+                    .movable(false);
+
+                suggestions_area.show(ui.ctx(), |ui| {
+                    ui.set_min_width(config.width);
+
+                    for (i, suggestion) in cache.predictions_cache.iter().enumerate() {
+                        let mut layout_job = egui::text::LayoutJob::default();
+                        let is_highlighted = Some(i) == state.suggestion_index;
+
+                        let mut style = TextFormat {
+                            font_id: FontId::new(14.0, egui::FontFamily::Monospace),
+                            color: Color32::WHITE,
+                            ..default()
+                        };
+
+                        if is_highlighted {
+                            style.underline = egui::Stroke::new(1., Color32::WHITE);
+                            style.background = Color32::from_black_alpha(128);
+                        }
+
+                        layout_job.append(suggestion, 0.0, style);
+                        ui.label(layout_job);
+                    }
+                });
+            }
+
+            handle_enter(
+                config,
+                cache,
+                state,
+                command_entered,
+                ui,
+                &text_edit_response,
+            );
+
+            // Handle up and down through history
+            if text_edit_response.has_focus()
+                && ui.input(|i| i.key_pressed(egui::Key::ArrowUp))
+                && state.history.len() > 1
+                && state.history_index < state.history.len() - 1
+            {
+                if state.history_index == 0 && !state.buf.trim().is_empty() {
+                    *state.history.get_mut(0).unwrap() = state.buf.clone();
+                }
+
+                state.history_index += 1;
+                let previous_item = state.history.get(state.history_index).unwrap().clone();
+                state.buf = previous_item.to_string();
+
+                set_cursor_pos(ui.ctx(), text_edit_response.id, state.buf.len());
+            } else if text_edit_response.has_focus()
+                && ui.input(|i| i.key_pressed(egui::Key::ArrowDown))
+                && state.history_index > 0
+            {
+                state.history_index -= 1;
+                let next_item = state.history.get(state.history_index).unwrap().clone();
+                state.buf = next_item.to_string();
+
+                set_cursor_pos(ui.ctx(), text_edit_response.id, state.buf.len());
+            }
+
+            // handle tab cycling through suggestions
+            if ui.input(|i| i.key_pressed(egui::Key::Tab)) && !cache.predictions_cache.is_empty() {
+                match &mut state.suggestion_index {
+                    Some(index) => {
+                        *index = (*index + 1) % cache.predictions_cache.len();
+                    }
+                    None => {
+                        state.suggestion_index = Some(0);
+                    }
+                }
+            }
+
+            // Focus on input while the console is open, unless searching
+            if console_open.open && !state.search.open {
+                ui.memory_mut(|m| m.request_focus(text_edit_response.id));
+            }
+        });
+    }
+}
+
 pub(crate) fn console_ui(
     mut egui_context: EguiContexts,
-    config: Res<ConsoleConfiguration>,
-    mut cache: ResMut<ConsoleCache>,
     mut keyboard_input_events: EventReader<KeyboardInput>,
-    mut state: ResMut<ConsoleState>,
-    command_entered: EventWriter<ConsoleCommandEntered>,
-    mut console_open: ResMut<ConsoleOpen>,
+    mut widget: ConsoleWidget,
 ) {
     let keyboard_input_events = keyboard_input_events.read().collect::<Vec<_>>();
 
@@ -495,172 +1123,426 @@ pub(crate) fn console_ui(
 
     let pressed = keyboard_input_events
         .iter()
-        .any(|code| console_key_pressed(code, &config.keys));
+        .any(|code| console_key_pressed(code, &widget.config.keys));
 
     // always close if console open
     // avoid opening console if typing in another text input
-    if pressed && (console_open.open || !ctx.wants_keyboard_input()) {
-        console_open.open = !console_open.open;
+    if pressed && (widget.console_open.open || !ctx.wants_keyboard_input()) {
+        widget.console_open.open = !widget.console_open.open;
     }
 
-    if console_open.open {
-        // Recompute predictions if the buffer changed
-        recompute_predictions(&mut state, &mut cache, config.num_suggestions);
-
-        egui::Window::new(&config.title_name)
-            .collapsible(config.collapsible)
-            .default_pos([config.left_pos, config.top_pos])
-            .default_size([config.width, config.height])
-            .resizable(config.resizable)
-            .movable(config.moveable)
-            .title_bar(config.show_title_bar)
-            .frame(egui::Frame {
-                fill: config.background_color,
-                ..Default::default()
-            })
-            .show(ctx, |ui| {
-                ui.style_mut().visuals.extreme_bg_color = config.background_color;
-                ui.style_mut().visuals.override_text_color = Some(config.foreground_color);
-
-                ui.vertical(|ui| {
-                    const WRITE_AREA_HEIGHT: f32 = 30.0;
-                    let scroll_height = ui.available_height() - WRITE_AREA_HEIGHT;
-                    // Scroll area
-                    ScrollArea::vertical()
-                        .auto_shrink([false, false])
-                        .stick_to_bottom(true)
-                        .max_height(scroll_height)
-                        .show(ui, |ui| {
-                            ui.vertical(|ui| {
-                                for line in &state.scrollback {
-                                    ui.label(style_ansi_text(line, &config));
-                                }
-                            });
-
-                            // Scroll to bottom if console just opened
-                            if console_open.is_changed() {
-                                ui.scroll_to_cursor(Some(Align::BOTTOM));
-                            }
-                        });
-
-                    // Separator
-                    ui.separator();
+    if widget.config.show_window {
+        show_window(ctx, &mut widget);
+    }
+    if !ctx.is_using_pointer() {
+        save_session(&widget.config, &mut widget.state, widget.console_open.open);
+    }
+}
 
-                    // Clear line on ctrl+c
-                    if ui.input(|i| i.modifiers.ctrl & i.key_pressed(egui::Key::C)) {
-                        state.buf.clear();
-                        return;
+fn show_window(ctx: &Context, widget: &mut ConsoleWidget) {
+    let config = &widget.config;
+    match config.presentation {
+        ConsolePresentation::Window if widget.console_open.open => {
+            let pos = widget
+                .state
+                .window_pos
+                .unwrap_or(pos2(config.left_pos, config.top_pos));
+            let size = widget
+                .state
+                .window_size
+                .unwrap_or(vec2(config.width, config.height));
+            let response = egui::Window::new(&config.title_name)
+                .collapsible(config.collapsible)
+                .default_pos(pos)
+                .default_size(size)
+                .resizable(config.resizable)
+                .movable(config.moveable)
+                .title_bar(config.show_title_bar)
+                .frame(egui::Frame {
+                    fill: config.background_color,
+                    ..Default::default()
+                })
+                .show(ctx, |ui| {
+                    let size = ui.max_rect().size();
+                    widget.show(ui);
+                    size
+                });
+            if let Some(response) = response {
+                widget.state.window_pos = Some(response.response.rect.min);
+                if let Some(size) = response.inner {
+                    widget.state.window_size = Some(size);
+                }
+            }
+        }
+        ConsolePresentation::Window => {}
+        ConsolePresentation::DropDown { height, slide_time } => {
+            let id = Id::new("console_drop_down");
+            // Keeps showing the console while it slides out
+            let shown = ctx.animate_bool_with_time_and_easing(
+                id,
+                widget.console_open.open,
+                slide_time,
+                egui::emath::easing::cubic_out,
+            );
+            if shown <= 0.0 {
+                return;
+            }
+            let fill = config.background_color;
+            let margin = ctx.style().spacing.window_margin;
+            let rect = drop_down_rect(ctx.screen_rect(), height, shown);
+            egui::Area::new(id)
+                .order(egui::Order::Foreground)
+                .constrain(false)
+                .fixed_pos(rect.min)
+                .show(ctx, |ui| {
+                    egui::Frame {
+                        fill,
+                        inner_margin: margin,
+                        ..Default::default()
                     }
+                    .show(ui, |ui| {
+                        ui.set_min_size(rect.size() - margin.sum());
+                        ui.set_max_size(rect.size() - margin.sum());
+                        widget.show(ui);
+                    });
+                });
+        }
+    }
+}
+
+/// Area of the drop-down console, `shown` from `0.0` when hidden to `1.0` when fully slid in.
+fn drop_down_rect(screen: Rect, height: f32, shown: f32) -> Rect {
+    let height = screen.height() * height.clamp(0.0, 1.0);
+    let top = screen.top() - height * (1.0 - shown);
+    Rect::from_min_size(pos2(screen.left(), top), vec2(screen.width(), height))
+}
+
+/// Shows the filtered scrollback, laying out only the rows inside the viewport.
+///
+/// Lines are not wrapped so that the height of every entry is known without laying it out.
+fn scrollback_area(
+    ui: &mut egui::Ui,
+    state: &mut ConsoleState,
+    view: &ScrollbackView,
+    config: &ConsoleConfiguration,
+    max_height: f32,
+    scroll_to_bottom: bool,
+    scroll_to_row: Option<usize>,
+) -> Option<ConsoleLink> {
+    let total_rows = view.total_rows();
+    let row_height = ui.fonts(|fonts| fonts.row_height(&FontId::monospace(FONT_SIZE)));
+
+    let mut scroll_area = ScrollArea::both()
+        .auto_shrink([false, false])
+        .stick_to_bottom(true)
+        .max_height(max_height);
+    if let Some(row) = scroll_to_row {
+        // Center the row in the viewport
+        let offset = row as f32 * row_height - (max_height - row_height) / 2.0;
+        scroll_area = scroll_area.vertical_scroll_offset(offset.max(0.0));
+    } else if scroll_to_bottom {
+        scroll_area = scroll_area.vertical_scroll_offset(total_rows as f32 * row_height);
+    }
+
+    let ConsoleState {
+        scrollback,
+        search,
+        selection,
+        show_line_stamps,
+        ..
+    } = state;
+
+    // Dimmed column left of the lines, all stamps have the same width
+    let stamp_format = TextFormat {
+        font_id: FontId::monospace(FONT_SIZE),
+        color: config.foreground_color.gamma_multiply(0.5),
+        line_height: Some(row_height),
+        ..default()
+    };
+    let layout_stamp = |ui: &egui::Ui, stamp: &LineStamp| {
+        let job = LayoutJob::single_section(stamp.format(), stamp_format.clone());
+        ui.fonts(|fonts| fonts.layout_job(job))
+    };
+    let stamp_width = if config.record_line_stamps && *show_line_stamps {
+        let sample = LineStamp {
+            wall: UNIX_EPOCH,
+            elapsed: Duration::ZERO,
+            frame: 0,
+        };
+        layout_stamp(ui, &sample).size().x + ui.spacing().item_spacing.x
+    } else {
+        0.0
+    };
 
-                    // Clear history on ctrl+l
-                    if ui.input(|i| i.modifiers.ctrl & i.key_pressed(egui::Key::L)) {
-                        state.scrollback.clear();
-                        return;
+    let mut clicked_link = None;
+    let mut copy = None;
+    let mut toggle_fold = None;
+    let (pointer, press_origin) =
+        ui.input(|i| (i.pointer.interact_pos(), i.pointer.press_origin()));
+    ui.scope(|ui| {
+        // Rows are laid out back to back, multiline entries span several of them
+        ui.spacing_mut().item_spacing.y = 0.0;
+        scroll_area.show_rows(ui, row_height, total_rows, |ui, rows| {
+            let top = ui.max_rect().top() - rows.start as f32 * row_height;
+            let left = ui.max_rect().left();
+            for (id, row) in view.entries_in(rows) {
+                let Some(line) = scrollback.get_mut(id) else {
+                    continue;
+                };
+                let row_top = top + row as f32 * row_height;
+                if let Some(stamp) = line.stamp.filter(|_| stamp_width > 0.0) {
+                    let galley = layout_stamp(ui, &stamp);
+                    ui.painter()
+                        .galley(pos2(left, row_top), galley, stamp_format.color);
+                }
+                let galley = line.layout(|text| {
+                    let mut job = style_ansi_text(text, config);
+                    search.highlight(id, &mut job);
+                    style_links(&mut job, &find_links(text), ui.visuals().hyperlink_color);
+                    for section in &mut job.sections {
+                        // Keeps bold text from making its row taller than the others
+                        section.format.line_height = Some(row_height);
                     }
+                    ui.fonts(|fonts| fonts.layout_job(job))
+                });
+                let pos = pos2(left + stamp_width, row_top);
+                // Rows span the whole width, so dragging past the end of a line selects all of it
+                let size = vec2(galley.size().x.max(ui.max_rect().width()), galley.size().y);
+                let rect = Rect::from_min_size(pos, size);
+                let response = ui.allocate_rect(rect, Sense::click_and_drag());
+
+                let point_at = |pointer: Pos2| TextPoint {
+                    id,
+                    index: galley.cursor_from_pos(pointer - pos).ccursor.index,
+                };
+                if response.drag_started_by(egui::PointerButton::Primary) {
+                    if let Some(origin) = press_origin {
+                        selection.begin(point_at(origin));
+                    }
+                }
+                if let Some(pointer) = pointer.filter(|pointer| {
+                    selection.is_dragging() && (rect.top()..rect.bottom()).contains(&pointer.y)
+                }) {
+                    selection.extend(point_at(pointer));
+                }
+                if response.clicked() {
+                    selection.clear();
+                }
 
-                    // Input
-                    let text_edit = TextEdit::singleline(&mut state.buf)
-                        .desired_width(f32::INFINITY)
-                        .lock_focus(true)
-                        .font(egui::TextStyle::Monospace);
+                let hovered_link = response
+                    .hover_pos()
+                    .and_then(|pointer| link_at(&galley, pointer - pos, &line.display_text()));
+                if let Some(link) = hovered_link {
+                    ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+                    if response.clicked() {
+                        clicked_link = Some(link);
+                    }
+                }
 
-                    let text_edit_response = ui.add(text_edit);
+                response.context_menu(|ui| {
+                    copy = copy_menu(ui, id, !selection.is_empty()).or(copy);
+                });
 
-                    // show a few suggestions
-                    if text_edit_response.has_focus()
-                        && !state.buf.is_empty()
-                        && !cache.prediction_matches_buffer
+                let mut galley = galley;
+                if !selection.is_empty() {
+                    if let Some((from, to)) = selection.chars_of(id, galley.text().chars().count())
                     {
-                        // create the area to show suggestions
-                        let suggestions_area = egui::Area::new(ui.auto_id_with("suggestions"))
-                            .fixed_pos(ui.next_widget_position())
-                            .movable(false);
-
-                        suggestions_area.show(ui.ctx(), |ui| {
-                            ui.set_min_width(config.width);
-
-                            for (i, suggestion) in cache.predictions_cache.iter().enumerate() {
-                                let mut layout_job = egui::text::LayoutJob::default();
-                                let is_highlighted = Some(i) == state.suggestion_index;
-
-                                let mut style = TextFormat {
-                                    font_id: FontId::new(14.0, egui::FontFamily::Monospace),
-                                    color: Color32::WHITE,
-                                    ..default()
-                                };
-
-                                if is_highlighted {
-                                    style.underline = egui::Stroke::new(1., Color32::WHITE);
-                                    style.background = Color32::from_black_alpha(128);
-                                }
-
-                                layout_job.append(suggestion, 0.0, style);
-                                ui.label(layout_job);
-                            }
+                        let range = CursorRange::two(
+                            galley.from_ccursor(CCursor::new(from)),
+                            galley.from_ccursor(CCursor::new(to)),
+                        );
+                        paint_text_selection(&mut galley, ui.visuals(), &range, None);
+                    }
+                }
+                let line_end = pos.x + galley.size().x;
+                ui.painter().galley(pos, galley, config.foreground_color);
+
+                if let (
+                    Some(header),
+                    LineKind::Output {
+                        invocation: Some(invocation),
+                        ..
+                    },
+                ) = (&line.header, &line.kind)
+                {
+                    let (badge, color) = block_badge(header, config);
+                    let job = LayoutJob::single_section(
+                        badge,
+                        TextFormat {
+                            font_id: FontId::monospace(FONT_SIZE),
+                            color,
+                            line_height: Some(row_height),
+                            ..default()
+                        },
+                    );
+                    let badge = ui.fonts(|fonts| fonts.layout_job(job));
+                    let badge_pos = pos2(line_end + ui.spacing().item_spacing.x, row_top);
+                    let badge_rect = Rect::from_min_size(badge_pos, badge.size());
+                    let response = ui
+                        .interact(badge_rect, ui.id().with(("block", id)), Sense::click())
+                        .on_hover_text(if header.folded {
+                            "Show the output"
+                        } else {
+                            "Hide the output"
                         });
+                    if response.clicked() {
+                        toggle_fold = Some(*invocation);
                     }
+                    ui.painter().galley(badge_pos, badge, color);
+                }
+            }
+        });
+    });
 
-                    handle_enter(
-                        config,
-                        &cache,
-                        &mut state,
-                        command_entered,
-                        ui,
-                        &text_edit_response,
-                    );
+    if let Some(invocation) = toggle_fold {
+        scrollback.toggle_fold(invocation);
+    }
 
-                    // Handle up and down through history
-                    if text_edit_response.has_focus()
-                        && ui.input(|i| i.key_pressed(egui::Key::ArrowUp))
-                        && state.history.len() > 1
-                        && state.history_index < state.history.len() - 1
-                    {
-                        if state.history_index == 0 && !state.buf.trim().is_empty() {
-                            *state.history.get_mut(0).unwrap() = state.buf.clone();
-                        }
+    if !ui.input(|i| i.pointer.primary_down()) {
+        selection.end_drag();
+    }
+    if let Some(text) = copy.and_then(|copy| copy.text(scrollback, view, selection)) {
+        ui.ctx().copy_text(text);
+    }
+    clicked_link
+}
 
-                        state.history_index += 1;
-                        let previous_item = state.history.get(state.history_index).unwrap().clone();
-                        state.buf = previous_item.to_string();
+/// Text and color of the badge after an echoed command, like `[-] ok` or `[+] 3 lines failed`.
+fn block_badge(header: &BlockHeader, config: &ConsoleConfiguration) -> (String, Color32) {
+    let (status, color) = match header.status {
+        Some(BlockStatus::Ok) => ("ok", config.ansi_colors[2]),
+        Some(BlockStatus::Failed) => ("failed", config.ansi_colors[1]),
+        Some(BlockStatus::Cancelled) => ("cancelled", config.ansi_colors[3]),
+        None => ("", config.foreground_color.gamma_multiply(0.5)),
+    };
+    let badge = if header.folded {
+        let plural = if header.replies == 1 { "" } else { "s" };
+        format!("[+] {} line{plural} {status}", header.replies)
+    } else {
+        format!("[-] {status}")
+    };
+    (badge.trim_end().to_string(), color)
+}
 
-                        set_cursor_pos(ui.ctx(), text_edit_response.id, state.buf.len());
-                    } else if text_edit_response.has_focus()
-                        && ui.input(|i| i.key_pressed(egui::Key::ArrowDown))
-                        && state.history_index > 0
-                    {
-                        state.history_index -= 1;
-                        let next_item = state.history.get(state.history_index).unwrap().clone();
-                        state.buf = next_item.to_string();
+/// Shows the copy actions of a scrollback line, returning the one which was clicked.
+fn copy_menu(ui: &mut egui::Ui, id: u64, has_selection: bool) -> Option<CopyAction> {
+    let mut actions = vec![(CopyAction::Line(id), "Copy line")];
+    if has_selection {
+        actions.insert(0, (CopyAction::Selection, "Copy selection"));
+    }
+    actions.extend([
+        (CopyAction::Visible, "Copy visible"),
+        (CopyAction::All, "Copy all"),
+    ]);
+
+    let mut clicked = None;
+    for (action, label) in actions {
+        let button = ui.button(label);
+        let button = match action {
+            CopyAction::Visible => button.on_hover_text("Lines shown with the current filter"),
+            _ => button,
+        };
+        if button.clicked() {
+            clicked = Some(action);
+            ui.close_menu();
+        }
+    }
+    clicked
+}
 
-                        set_cursor_pos(ui.ctx(), text_edit_response.id, state.buf.len());
-                    }
+/// Shows the search field and its toggles.
+///
+/// Returns `Some(backwards)` when asked to jump to the next or previous match.
+fn search_bar(ui: &mut egui::Ui, search: &mut ScrollbackSearch, focus: bool) -> Option<bool> {
+    let mut jump = None;
+    ui.horizontal(|ui| {
+        let response = ui.add(
+            TextEdit::singleline(&mut search.query)
+                .hint_text("search")
+                .desired_width(200.0)
+                .font(egui::TextStyle::Monospace),
+        );
+        if focus {
+            response.request_focus();
+        }
+        if response.lost_focus() {
+            if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                jump = Some(ui.input(|i| i.modifiers.shift));
+                response.request_focus();
+            } else if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                search.open = false;
+            }
+        }
 
-                    // handle tab cycling through suggestions
-                    if ui.input(|i| i.key_pressed(egui::Key::Tab))
-                        && !cache.predictions_cache.is_empty()
-                    {
-                        match &mut state.suggestion_index {
-                            Some(index) => {
-                                *index = (*index + 1) % cache.predictions_cache.len();
-                            }
-                            None => {
-                                state.suggestion_index = Some(0);
-                            }
-                        }
-                    }
+        ui.toggle_value(&mut search.match_case, "Aa")
+            .on_hover_text("Match case");
+        ui.toggle_value(&mut search.regex, ".*")
+            .on_hover_text("Regular expression");
+        if ui.button("prev").clicked() {
+            jump = Some(true);
+        }
+        if ui.button("next").clicked() {
+            jump = Some(false);
+        }
+        if ui.button("close").clicked() {
+            search.open = false;
+        }
 
-                    // Focus on input
-                    ui.memory_mut(|m| m.request_focus(text_edit_response.id));
-                });
-            });
-    }
+        if let Some(error) = &search.error {
+            ui.label("invalid regex").on_hover_text(error);
+        } else if !search.query.is_empty() {
+            ui.label(format!(
+                "{}/{}",
+                search.current_position().unwrap_or(0),
+                search.match_count()
+            ));
+        }
+    });
+    jump
+}
+
+fn channel_tabs(ui: &mut egui::Ui, channels: &[String], selected: &mut Option<String>) {
+    ui.horizontal(|ui| {
+        ui.selectable_value(selected, None, "all");
+        for channel in channels {
+            ui.selectable_value(selected, Some(channel.clone()), channel);
+        }
+    });
+}
+
+fn filter_toolbar(
+    ui: &mut egui::Ui,
+    filter: Option<&mut ScrollbackFilter>,
+    show_line_stamps: Option<&mut bool>,
+) {
+    ui.horizontal(|ui| {
+        if let Some(filter) = filter {
+            for level in LEVELS {
+                let mut visible = filter.level_visible(level);
+                if ui
+                    .toggle_value(&mut visible, level.as_str().to_lowercase())
+                    .changed()
+                {
+                    filter.set_level_visible(level, visible);
+                }
+            }
+            ui.separator();
+            ui.toggle_value(&mut filter.show_logs, "logs");
+            ui.toggle_value(&mut filter.show_output, "output");
+            ui.separator();
+        }
+        if let Some(show_line_stamps) = show_line_stamps {
+            ui.toggle_value(show_line_stamps, "stamps")
+                .on_hover_text("Time, elapsed game time and frame each line arrived in");
+        }
+    });
 }
 
 fn handle_enter(
-    config: Res<'_, ConsoleConfiguration>,
-    cache: &ResMut<'_, ConsoleCache>,
-    state: &mut ResMut<'_, ConsoleState>,
-    mut command_entered: EventWriter<'_, ConsoleCommandEntered>,
+    config: &ConsoleConfiguration,
+    cache: &ConsoleCache,
+    state: &mut ConsoleState,
+    command_entered: &mut EventWriter<'_, ConsoleCommandEntered>,
     ui: &mut egui::Ui,
     text_edit_response: &egui::Response,
 ) {
@@ -678,18 +1560,40 @@ fn handle_enter(
         }
 
         if state.buf.trim().is_empty() {
-            state.scrollback.push(String::new());
+            state.scrollback.push_output("");
         } else {
+            // The echoed line starts the block of the command's replies
+            let invocation = InvocationId::next();
             let msg = format!("{}{}", config.symbol, state.buf);
-            state.scrollback.push(msg);
+            state.scrollback.push_command(msg, invocation);
             let cmd_string = state.buf.clone();
             state.history.insert(1, cmd_string);
             if state.history.len() > config.history_size + 1 {
                 state.history.pop_back();
             }
             state.history_index = 0;
+            // Show the replies, unless they go to the channel of the current tab
+            if state
+                .filter
+                .channel
+                .as_ref()
+                .is_some_and(|channel| channel != COMMANDS_CHANNEL)
+            {
+                state.filter.channel = None;
+            }
 
-            let mut args = Shlex::new(&state.buf).collect::<Vec<_>>();
+            let buf = mem::take(&mut state.buf);
+            let (command_line, pipeline) = match Pipeline::parse(&buf) {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    state.scrollback.push_reply(err, Some(invocation));
+                    state
+                        .scrollback
+                        .set_block_status(invocation, BlockStatus::Failed);
+                    return;
+                }
+            };
+            let mut args = Shlex::new(command_line).collect::<Vec<_>>();
 
             if !args.is_empty() {
                 let command_name = args.remove(0);
@@ -698,32 +1602,197 @@ fn handle_enter(
                 let command = config.commands.get(command_name.as_str());
 
                 if command.is_some() {
-                    command_entered.write(ConsoleCommandEntered { command_name, args });
+                    if let Some(pipeline) = pipeline {
+                        state.pipelines.insert(invocation, pipeline);
+                    }
+                    command_entered.write(ConsoleCommandEntered {
+                        command_name,
+                        args,
+                        invocation,
+                    });
                 } else {
                     debug!(
                         "Command not recognized, recognized commands: `{:?}`",
                         config.commands.keys().collect::<Vec<_>>()
                     );
 
-                    state.scrollback.push("error: Invalid command".into());
+                    state
+                        .scrollback
+                        .push_reply("error: Invalid command", Some(invocation));
+                    state
+                        .scrollback
+                        .set_block_status(invocation, BlockStatus::Failed);
                 }
             }
-
-            state.buf.clear();
         }
     }
 }
 
 pub(crate) fn receive_console_line(
+    config: Res<ConsoleConfiguration>,
     mut console_state: ResMut<ConsoleState>,
     mut events: EventReader<PrintConsoleLine>,
+    mut reply_times: ResMut<ReplyTimes>,
+    mut updates: EventReader<UpdateConsoleLine>,
+    mut tasks_started: EventReader<CommandTaskStarted>,
+    mut log_records: EventReader<LogRecord>,
 ) {
-    for event in events.read() {
-        let event: &PrintConsoleLine = event;
-        console_state.scrollback.push(event.line.clone());
+    let ConsoleState {
+        scrollback,
+        pipelines,
+        line_handles,
+        tasks,
+        ..
+    } = &mut *console_state;
+    scrollback.configure(&config);
+
+    // Both streams are in order, merge them by when the replies and records were written. Lines
+    // not printed through `ConsoleCommand` are only known from now on.
+    let mut log_records = log_records.read().peekable();
+    for (event, id) in events.read_with_id() {
+        let written = reply_times.0.remove(&id).unwrap_or_else(SystemTime::now);
+        while let Some(record) = log_records.next_if(|record| record.timestamp < written) {
+            scrollback.push_log(record);
+        }
+        match (
+            event.invocation.and_then(|id| pipelines.get_mut(&id)),
+            event.handle,
+        ) {
+            (Some(pipeline), _) => pipeline.capture(&event.line),
+            (None, Some(handle)) => {
+                let id = scrollback.push_updatable(
+                    event.line.clone(),
+                    event.invocation,
+                    event.channel.clone(),
+                );
+                line_handles.insert(handle, id);
+            }
+            (None, None) => scrollback.push_to_channel(
+                event.line.clone(),
+                event.invocation,
+                event.channel.clone(),
+            ),
+        }
+    }
+
+    tasks.extend(
+        tasks_started
+            .read()
+            .map(|CommandTaskStarted(task)| task.clone()),
+    );
+    tasks.retain(|task| {
+        let poll = task.poll();
+        for line in poll
+            .replies
+            .into_iter()
+            .chain(poll.status.map(String::from))
+        {
+            print_output(scrollback, pipelines, task.invocation(), line);
+        }
+        !poll.done
+    });
+
+    for UpdateConsoleLine { handle, update } in updates.read() {
+        let Some(&id) = line_handles.get(handle) else {
+            continue;
+        };
+        match update {
+            LineUpdate::Replace(line) => {
+                if !scrollback.replace(id, line.clone()) {
+                    line_handles.remove(handle);
+                }
+            }
+            LineUpdate::Remove => {
+                scrollback.remove(id);
+                line_handles.remove(handle);
+            }
+        }
+    }
+    if !line_handles.is_empty() {
+        let first_id = scrollback.first_id();
+        line_handles.retain(|_, id| *id >= first_id);
+    }
+
+    for record in log_records {
+        scrollback.push_log(record);
+    }
+
+    // Show the filtered output once the command had a chance to reply, and finished running
+    let finished = pipelines
+        .iter_mut()
+        .filter(|(id, _)| !tasks.iter().any(|task| task.invocation() == Some(**id)))
+        .filter_map(|(id, pipeline)| pipeline.tick().then_some(*id))
+        .collect::<Vec<_>>();
+    for id in finished {
+        if let Some(pipeline) = pipelines.remove(&id) {
+            for line in pipeline.finish() {
+                scrollback.push_reply(line, Some(id));
+            }
+        }
     }
 }
 
+/// Sets the stamp of the scrollback entries appended this frame, if configured.
+pub(crate) fn update_line_stamp(
+    config: Res<ConsoleConfiguration>,
+    time: Option<Res<Time>>,
+    frame_count: Option<Res<FrameCount>>,
+    mut state: ResMut<ConsoleState>,
+) {
+    let stamp = config.record_line_stamps.then(|| LineStamp {
+        wall: SystemTime::now(),
+        elapsed: time.map_or(Duration::ZERO, |time| time.elapsed()),
+        frame: frame_count.map_or(0, |frame_count| frame_count.0),
+    });
+    state.scrollback.set_stamp(stamp);
+}
+
+/// Prints a line of a command, unless its output goes through a pipeline.
+fn print_output(
+    scrollback: &mut Scrollback,
+    pipelines: &mut BTreeMap<InvocationId, Pipeline>,
+    invocation: Option<InvocationId>,
+    line: String,
+) {
+    match invocation.and_then(|id| pipelines.get_mut(&id)) {
+        Some(pipeline) => pipeline.capture(&line),
+        None => scrollback.push_reply(line, invocation),
+    }
+}
+
+fn cancel_tasks(state: &mut ConsoleState) {
+    for task in mem::take(&mut state.tasks) {
+        task.cancel();
+        // Replies which arrived before the cancellation are still shown
+        let poll = task.poll();
+        for line in poll.replies.into_iter().chain(["[cancelled]".to_string()]) {
+            print_output(
+                &mut state.scrollback,
+                &mut state.pipelines,
+                task.invocation(),
+                line,
+            );
+        }
+    }
+}
+
+/// Shows the running commands above the prompt, with their progress if they report any.
+fn running_tasks_label(ui: &mut egui::Ui, tasks: &[CommandTask]) {
+    let running = tasks
+        .iter()
+        .map(|task| match task.progress() {
+            Some(progress) => format!("{} {:.0}%", task.name(), progress * 100.0),
+            None => task.name().to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    ui.label(
+        egui::RichText::new(format!("Running: {running} (Ctrl+C to cancel)"))
+            .monospace()
+            .weak(),
+    );
+}
+
 fn console_key_pressed(keyboard_input: &KeyboardInput, configured_keys: &[KeyCode]) -> bool {
     if !keyboard_input.state.is_pressed() {
         return false;
@@ -754,6 +1823,115 @@ mod tests {
 
     use super::*;
 
+    fn console_world() -> World {
+        let mut world = World::new();
+        world.init_resource::<ConsoleConfiguration>();
+        world.init_resource::<ConsoleState>();
+        world.init_resource::<ReplyTimes>();
+        world.init_resource::<Events<ConsoleCommandEntered>>();
+        world.init_resource::<Events<PrintConsoleLine>>();
+        world.init_resource::<Events<UpdateConsoleLine>>();
+        world.init_resource::<Events<CommandTaskStarted>>();
+        world.init_resource::<Events<LogRecord>>();
+        world
+    }
+
+    #[test]
+    fn test_invalid_arguments_fail_the_command() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        use crate as bevy_console;
+
+        #[derive(clap::Parser, crate::ConsoleCommand)]
+        #[command(name = "spawn")]
+        struct SpawnCommand {
+            count: u32,
+        }
+
+        for (args, status) in [("lots", BlockStatus::Failed), ("--help", BlockStatus::Ok)] {
+            let mut world = console_world();
+            let command = ConsoleCommandEntered::new("spawn", vec![args.to_string()]);
+            world
+                .resource_mut::<ConsoleState>()
+                .scrollback
+                .push_command(format!("spawn {args}"), command.invocation);
+            world.send_event(command);
+
+            world
+                .run_system_once(|mut spawn: ConsoleCommand<SpawnCommand>| {
+                    assert!(matches!(spawn.take(), Some(Err(_))));
+                })
+                .unwrap();
+            world.run_system_once(receive_console_line).unwrap();
+            let state = world.resource::<ConsoleState>();
+            let header = state.scrollback.iter().next().unwrap().header.clone();
+            assert_eq!(header.unwrap().status, Some(status), "{args}");
+        }
+    }
+
+    #[test]
+    fn test_line_stamp_without_time_plugin() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let mut world = console_world();
+        world
+            .resource_mut::<ConsoleConfiguration>()
+            .record_line_stamps = true;
+        world.run_system_once(update_line_stamp).unwrap();
+    }
+
+    #[test]
+    fn test_replies_and_logs_keep_their_order() {
+        use bevy::ecs::system::RunSystemOnce;
+        use bevy::log::Level;
+
+        let mut world = console_world();
+
+        let written = SystemTime::now();
+        let record = |message: &str, timestamp: SystemTime| LogRecord {
+            level: Level::INFO,
+            target: "mygame".to_string(),
+            module_path: None,
+            file: None,
+            line: None,
+            spans: Vec::new(),
+            message: message.to_string(),
+            fields: Vec::new(),
+            timestamp,
+        };
+        let before = record("before", written - Duration::from_millis(1));
+        let after = record("after", written + Duration::from_millis(1));
+        let id = world.send_event(PrintConsoleLine::new("reply".to_string()));
+        world
+            .resource_mut::<ReplyTimes>()
+            .0
+            .insert(id.unwrap(), written);
+        world.send_event_batch([before, after]);
+
+        world.run_system_once(receive_console_line).unwrap();
+        let state = world.resource::<ConsoleState>();
+        let lines = state
+            .scrollback
+            .iter()
+            .map(|line| crate::color::plain_text(&line.display_text()))
+            .collect::<Vec<_>>();
+        assert!(lines[0].ends_with("before"));
+        assert_eq!(lines[1], "reply");
+        assert!(lines[2].ends_with("after"));
+    }
+
+    #[test]
+    fn test_drop_down_spans_the_window_width() {
+        let screen = Rect::from_min_size(Pos2::ZERO, vec2(1280.0, 720.0));
+
+        let rect = drop_down_rect(screen, 0.5, 1.0);
+        assert_eq!(rect, Rect::from_min_max(Pos2::ZERO, pos2(1280.0, 360.0)));
+
+        let rect = drop_down_rect(screen, 2.0, 0.25);
+        assert_eq!(rect.min, pos2(0.0, -540.0));
+        assert_eq!(rect.size(), vec2(1280.0, 720.0));
+    }
+
     #[test]
     fn test_console_key_pressed_scan_code() {
         let input = KeyboardInput {
@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy_egui::egui::Color32;
+
+use crate::color::{parse_ansi_styled_str, TextFormattingOverride};
+use crate::console::ConsoleState;
+use crate::scrollback::Scrollback;
+use crate::ConsoleConfiguration;
+
+/// Writes the scrollback to a standalone HTML file, keeping its colors and styles.
+///
+/// The `export_html` console command does the same from within the console.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_console::ExportConsoleHtml;
+/// fn export_on_f12(keys: Res<ButtonInput<KeyCode>>, mut export: EventWriter<ExportConsoleHtml>) {
+///     if keys.just_pressed(KeyCode::F12) {
+///         export.write(ExportConsoleHtml {
+///             path: "console.html".into(),
+///         });
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Event)]
+pub struct ExportConsoleHtml {
+    /// File to write, replaced if it exists
+    pub path: PathBuf,
+}
+
+pub(crate) fn export_console_html(
+    mut events: EventReader<ExportConsoleHtml>,
+    state: Res<ConsoleState>,
+    config: Res<ConsoleConfiguration>,
+) {
+    for ExportConsoleHtml { path } in events.read() {
+        if let Err(err) = write_html(path, &state.scrollback, &config) {
+            error!("Failed to export console to {}: {err}", path.display());
+        }
+    }
+}
+
+/// Writes all scrollback entries as HTML, returning the number of entries written.
+pub(crate) fn write_html(
+    path: &Path,
+    scrollback: &Scrollback,
+    config: &ConsoleConfiguration,
+) -> io::Result<usize> {
+    let lines = scrollback
+        .iter()
+        .map(|line| line.display_text())
+        .collect::<Vec<_>>();
+    fs::write(path, scrollback_html(&lines, config))?;
+    Ok(lines.len())
+}
+
+fn scrollback_html(lines: &[impl AsRef<str>], config: &ConsoleConfiguration) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(html, "<title>{}</title>", escape(&config.title_name));
+    html.push_str("</head>\n");
+    // The window background is usually translucent, only its color is kept
+    let _ = writeln!(
+        html,
+        "<body style=\"margin:0;background:{};color:{}\">",
+        css_color(config.background_color),
+        css_color(config.foreground_color)
+    );
+    html.push_str("<pre style=\"margin:0;padding:8px;font-family:monospace;font-size:14px\">");
+    for line in lines {
+        line_html(line.as_ref(), &config.ansi_colors, &mut html);
+        html.push('\n');
+    }
+    html.push_str("</pre>\n</body>\n</html>\n");
+    html
+}
+
+fn line_html(line: &str, palette: &[Color32; 16], html: &mut String) {
+    for (text, overrides) in parse_ansi_styled_str(line, palette) {
+        if text.is_empty() {
+            continue;
+        }
+        let style = css_style(&overrides);
+        if style.is_empty() {
+            html.push_str(&escape(text));
+        } else {
+            let _ = write!(html, "<span style=\"{style}\">{}</span>", escape(text));
+        }
+    }
+}
+
+/// Inline CSS matching the way the console renders the overrides.
+fn css_style(overrides: &HashSet<TextFormattingOverride>) -> String {
+    let mut style = String::new();
+    let mut decorations = Vec::new();
+    for o in overrides {
+        match o {
+            TextFormattingOverride::Foreground(color) => {
+                let _ = write!(style, "color:{};", css_color(*color));
+            }
+            TextFormattingOverride::Background(color) => {
+                let _ = write!(style, "background:{};", css_color(*color));
+            }
+            _ => {}
+        }
+    }
+    if overrides.contains(&TextFormattingOverride::Bold) {
+        style.push_str("font-weight:bold;");
+    }
+    if overrides.contains(&TextFormattingOverride::Dim) {
+        style.push_str("opacity:0.5;");
+    }
+    if overrides.contains(&TextFormattingOverride::Italic) {
+        style.push_str("font-style:italic;");
+    }
+    if overrides.contains(&TextFormattingOverride::Underline) {
+        decorations.push("underline");
+    }
+    if overrides.contains(&TextFormattingOverride::Strikethrough) {
+        decorations.push("line-through");
+    }
+    if !decorations.is_empty() {
+        let _ = write!(style, "text-decoration:{};", decorations.join(" "));
+    }
+    style
+}
+
+fn css_color(color: Color32) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b())
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::color::DEFAULT_ANSI_COLORS;
+
+    fn html_of(line: &str) -> String {
+        let mut html = String::new();
+        line_html(line, &DEFAULT_ANSI_COLORS, &mut html);
+        html
+    }
+
+    #[test]
+    fn test_plain_text_is_escaped() {
+        assert_eq!(
+            html_of("a < b && \"c\""),
+            "a &lt; b &amp;&amp; &quot;c&quot;"
+        );
+    }
+
+    #[test]
+    fn test_colors_are_kept() {
+        assert_eq!(
+            html_of("\u{1b}[31merror\u{1b}[0m: oops"),
+            "<span style=\"color:#de382b;\">error</span>: oops"
+        );
+    }
+
+    #[test]
+    fn test_styles_are_kept() {
+        assert_eq!(
+            html_of("\u{1b}[1mbold\u{1b}[0m"),
+            "<span style=\"font-weight:bold;\">bold</span>"
+        );
+        assert_eq!(
+            css_style(&HashSet::from([
+                TextFormattingOverride::Underline,
+                TextFormattingOverride::Strikethrough
+            ])),
+            "text-decoration:underline line-through;"
+        );
+    }
+
+    #[test]
+    fn test_document_has_console_colors() {
+        let config = ConsoleConfiguration::default();
+        let html = scrollback_html(&["$ help"], &config);
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Console</title>"));
+        assert!(html.contains("background:#000000;color:#dcdcdc"));
+        assert!(html.contains(">$ help\n</pre>"));
+    }
+}
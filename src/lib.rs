@@ -2,29 +2,57 @@
 #![deny(missing_docs)]
 
 use bevy::prelude::*;
+use bevy::time::TimeSystem;
 pub use bevy_console_derive::ConsoleCommand;
 use bevy_egui::{EguiContextPass, EguiPlugin};
 use console::ConsoleCache;
 use trie_rs::TrieBuilder;
 
 use crate::commands::clear::{clear_command, ClearCommand};
+use crate::commands::condump::{condump_command, CondumpCommand};
+use crate::commands::diag::{
+    diag_command, fps_command, update_diag_watches, DiagCommand, DiagWatches, FpsCommand,
+};
 use crate::commands::exit::{exit_command, ExitCommand};
+use crate::commands::export::{export_html_command, ExportHtmlCommand};
+use crate::commands::filter::{filter_command, FilterCommand};
 use crate::commands::help::{help_command, HelpCommand};
+use crate::commands::time::{
+    advance_time_step, pause_command, pause_time_while_open, step_command, timescale_command,
+    unpause_command, PauseCommand, StepCommand, TimeControlState, TimescaleCommand, UnpauseCommand,
+};
 pub use crate::console::{
     AddConsoleCommand, Command, ConsoleCommand, ConsoleCommandEntered, ConsoleConfiguration,
-    ConsoleOpen, NamedCommand, PrintConsoleLine,
+    ConsoleOpen, ConsolePresentation, ConsoleWidget, InvocationId, LineHandle, LineUpdate,
+    NamedCommand, PrintConsoleLine, UpdateConsoleLine,
 };
+pub use crate::export::ExportConsoleHtml;
+pub use crate::link::{ConsoleLink, ConsoleLinkClicked};
 pub use crate::log::*;
+pub use crate::task::CommandTask;
 
-use crate::console::{console_ui, receive_console_line, ConsoleState};
+use crate::console::{
+    console_ui, receive_console_line, update_line_stamp, ConsoleState, ReplyTimes,
+};
+use crate::export::export_console_html;
+use crate::session::load_session;
+use crate::task::CommandTaskStarted;
 pub use clap;
 
 // mod color;
 mod color;
 mod commands;
 mod console;
+mod export;
+mod link;
 mod log;
 mod macros;
+mod pipe;
+mod scrollback;
+mod search;
+mod selection;
+mod session;
+mod task;
 /// Console plugin.
 pub struct ConsolePlugin;
 
@@ -72,13 +100,37 @@ impl Plugin for ConsolePlugin {
             .init_resource::<ConsoleState>()
             .init_resource::<ConsoleOpen>()
             .init_resource::<ConsoleCache>()
+            .init_resource::<ReplyTimes>()
+            .init_resource::<TimeControlState>()
+            .init_resource::<DiagWatches>()
             .add_event::<ConsoleCommandEntered>()
             .add_event::<PrintConsoleLine>()
+            .add_event::<UpdateConsoleLine>()
+            .add_event::<CommandTaskStarted>()
+            .add_event::<LogRecord>()
+            .add_event::<ExportConsoleHtml>()
+            .add_event::<ConsoleLinkClicked>()
             .add_console_command::<ClearCommand, _>(clear_command)
             .add_console_command::<ExitCommand, _>(exit_command)
             .add_console_command::<HelpCommand, _>(help_command)
+            .add_console_command::<FilterCommand, _>(filter_command)
+            .add_console_command::<CondumpCommand, _>(condump_command)
+            .add_console_command::<ExportHtmlCommand, _>(export_html_command)
+            .add_console_command::<PauseCommand, _>(pause_command)
+            .add_console_command::<UnpauseCommand, _>(unpause_command)
+            .add_console_command::<TimescaleCommand, _>(timescale_command)
+            .add_console_command::<StepCommand, _>(step_command)
+            .add_console_command::<DiagCommand, _>(diag_command)
+            .add_console_command::<FpsCommand, _>(fps_command)
             // after per-command startup
-            .add_systems(Startup, init.after(ConsoleSet::Startup))
+            .add_systems(Startup, (init, load_session).after(ConsoleSet::Startup))
+            .add_systems(First, advance_time_step.before(TimeSystem))
+            .add_systems(First, update_line_stamp.after(TimeSystem))
+            .add_systems(PostUpdate, pause_time_while_open)
+            .add_systems(
+                Update,
+                (update_diag_watches, export_console_html).in_set(ConsoleSet::PostCommands),
+            )
             .add_systems(
                 EguiContextPass,
                 (
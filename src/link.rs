@@ -0,0 +1,206 @@
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use bevy::prelude::*;
+use bevy_egui::egui::text::LayoutJob;
+use bevy_egui::egui::{Color32, Galley, Stroke, Vec2};
+use regex::Regex;
+
+use crate::color::{restyle_ranges, tokenize, Token};
+
+/// URLs, or `path:line` and `path:line:column` references to files with an extension
+static DETECTED_LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?x)
+        (?P<url>(?:https?|file)://[^\s<>"'`]+)
+        | (?P<path>(?:[A-Za-z]:[/\\]|/)?(?:[\w.\-]+[/\\])*[\w\-]+\.[A-Za-z][A-Za-z0-9]*)
+          :(?P<line>\d+)(?::(?P<column>\d+))?"#,
+    )
+    .expect("link pattern is valid")
+});
+
+/// Target of a link in the scrollback
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConsoleLink {
+    /// URI of an OSC 8 hyperlink, or a detected `http://`, `https://` or `file://` URL
+    Url(String),
+    /// Detected file reference, like the location in a panic message
+    File {
+        /// Path as printed, relative paths are not resolved
+        path: PathBuf,
+        /// Line number, starting at one
+        line: u32,
+        /// Column number, starting at one
+        column: Option<u32>,
+    },
+}
+
+/// Sent when a link in the scrollback is clicked.
+///
+/// Links come from OSC 8 hyperlink escapes (`ESC]8;;URI ESC\ text ESC]8;; ESC\`), and from URLs
+/// and `path:line` references found in the text. Nothing is opened by the console itself.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_console::{ConsoleLink, ConsoleLinkClicked};
+/// fn open_links(mut clicks: EventReader<ConsoleLinkClicked>) {
+///     for ConsoleLinkClicked { link } in clicks.read() {
+///         match link {
+///             ConsoleLink::Url(url) => info!("open {url} in the browser"),
+///             ConsoleLink::File { path, line, .. } => {
+///                 info!("open {} at line {line} in the editor", path.display())
+///             }
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Event)]
+pub struct ConsoleLinkClicked {
+    /// The link which was clicked
+    pub link: ConsoleLink,
+}
+
+/// Finds the links in a line, as sorted byte ranges into its plain text.
+pub(crate) fn find_links(ansi_text: &str) -> Vec<(Range<usize>, ConsoleLink)> {
+    let mut plain = String::with_capacity(ansi_text.len());
+    let mut links = Vec::new();
+    let mut open_link: Option<(usize, &str)> = None;
+    for token in tokenize(ansi_text).chain([Token::Link("")]) {
+        match token {
+            Token::Text(text) => plain.push_str(text),
+            Token::Link(uri) => {
+                if let Some((start, uri)) = open_link.take() {
+                    if start < plain.len() {
+                        links.push((start..plain.len(), ConsoleLink::Url(uri.to_string())));
+                    }
+                }
+                if !uri.is_empty() {
+                    open_link = Some((plain.len(), uri));
+                }
+            }
+            Token::Escape(_) | Token::Csi { .. } => {}
+        }
+    }
+
+    for captures in DETECTED_LINK.captures_iter(&plain) {
+        let (range, link) = if let Some(url) = captures.name("url") {
+            let start = url.start();
+            let url = url
+                .as_str()
+                .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '}']);
+            (start..start + url.len(), ConsoleLink::Url(url.to_string()))
+        } else {
+            let (Some(path), Some(line)) = (captures.name("path"), captures.name("line")) else {
+                continue;
+            };
+            let Ok(line) = line.as_str().parse() else {
+                continue;
+            };
+            let link = ConsoleLink::File {
+                path: path.as_str().into(),
+                line,
+                column: captures
+                    .name("column")
+                    .and_then(|column| column.as_str().parse().ok()),
+            };
+            (captures.get(0).map_or(0..0, |m| m.range()), link)
+        };
+        // Text of hyperlinks is not searched for links
+        if !links
+            .iter()
+            .any(|(other, _)| range.start < other.end && other.start < range.end)
+        {
+            links.push((range, link));
+        }
+    }
+
+    links.sort_by_key(|(range, _)| range.start);
+    links
+}
+
+/// Underlines the links in the layout of a line, whose text must be the plain text.
+pub(crate) fn style_links(
+    job: &mut LayoutJob,
+    links: &[(Range<usize>, ConsoleLink)],
+    color: Color32,
+) {
+    let ranges = links
+        .iter()
+        .map(|(range, _)| range.clone())
+        .collect::<Vec<_>>();
+    restyle_ranges(job, &ranges, |_, format| {
+        format.color = color;
+        format.underline = Stroke::new(1.0, color);
+    });
+}
+
+/// Returns the link under `pos`, relative to the layout of the line with the given text.
+pub(crate) fn link_at(galley: &Galley, pos: Vec2, ansi_text: &str) -> Option<ConsoleLink> {
+    let cursor = galley.cursor_from_pos(pos);
+    let mut index = cursor.ccursor.index;
+    // The cursor is the boundary closest to `pos`, the character is before it if it's to the right
+    if pos.x < galley.pos_from_cursor(&cursor).min.x {
+        index = index.checked_sub(1)?;
+    }
+    let (byte, _) = galley.text().char_indices().nth(index)?;
+    find_links(ansi_text)
+        .into_iter()
+        .find(|(range, _)| range.contains(&byte))
+        .map(|(_, link)| link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, line: u32, column: Option<u32>) -> ConsoleLink {
+        ConsoleLink::File {
+            path: path.into(),
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn test_osc8_hyperlinks() {
+        let text = "see \u{1b}]8;;https://bevyengine.org\u{1b}\\\u{1b}[4mdocs\u{1b}[0m\u{1b}]8;;\u{7} here";
+        assert_eq!(
+            find_links(text),
+            vec![(4..8, ConsoleLink::Url("https://bevyengine.org".into()))]
+        );
+    }
+
+    #[test]
+    fn test_detects_urls_and_file_references() {
+        let text = "panicked at src/main.rs:12:5: see https://docs.rs/bevy.";
+        assert_eq!(
+            find_links(text),
+            vec![
+                (12..28, file("src/main.rs", 12, Some(5))),
+                (34..54, ConsoleLink::Url("https://docs.rs/bevy".into())),
+            ]
+        );
+        assert_eq!(
+            find_links(r"C:\game\assets\level.ron:3"),
+            vec![(0..26, file(r"C:\game\assets\level.ron", 3, None))]
+        );
+    }
+
+    #[test]
+    fn test_ignores_numbers_and_times() {
+        assert!(find_links("00:00:01.250 connected to 127.0.0.1:8080 in 1.5s").is_empty());
+        assert!(find_links("bevy_render::renderer: AdapterInfo").is_empty());
+    }
+
+    #[test]
+    fn test_hyperlink_text_is_not_detected() {
+        let text = "\u{1b}]8;;file:///log.txt\u{7}src/lib.rs:1\u{1b}]8;;\u{7}";
+        assert_eq!(
+            find_links(text),
+            vec![(0..12, ConsoleLink::Url("file:///log.txt".into()))]
+        );
+    }
+}
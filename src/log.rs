@@ -1,119 +1,541 @@
 use std::{
-    io::{BufRead, Write},
-    sync::{Arc, Mutex},
+    fmt::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex, PoisonError,
+    },
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use bevy::{
     app::{App, Update},
-    log::tracing_subscriber::{self, EnvFilter, Layer, Registry},
-    prelude::{EventWriter, IntoScheduleConfigs, ResMut, Resource},
+    log::{
+        tracing::{
+            field::{Field, Visit},
+            span::{Attributes, Id, Record},
+            Event as TracingEvent, Subscriber,
+        },
+        tracing_subscriber::{
+            self, filter::ParseError, layer::Context, registry::LookupSpan, reload, EnvFilter,
+            Layer, Registry,
+        },
+        warn, Level,
+    },
+    prelude::{Event, EventWriter, IntoScheduleConfigs, Res, Resource},
 };
 
-use crate::{ConsoleSet, PrintConsoleLine};
+use crate::commands::log_filter::{
+    log_filter_command, log_level_command, LogFilterCommand, LogLevelCommand,
+};
+use crate::{AddConsoleCommand, ConsoleSet};
+
+/// Directives used by [make_layer], the levels the `LogPlugin` uses by default
+pub const DEFAULT_CONSOLE_FILTER: &str = "info,wgpu=error,naga=warn";
+
+/// Number of log records buffered between two console updates by [make_layer] and
+/// [make_filtered_layer]. Records logged while the buffer is full are dropped.
+pub const DEFAULT_LOG_BUFFER_CAPACITY: usize = 1024;
+
+/// A log event captured by the console layer
+#[derive(Clone, Debug, Event, PartialEq)]
+pub struct LogRecord {
+    /// Verbosity level
+    pub level: Level,
+    /// Target of the event, usually the module path
+    pub target: String,
+    /// Module the event was emitted from
+    pub module_path: Option<String>,
+    /// Source file the event was emitted from
+    pub file: Option<String>,
+    /// Line in [`LogRecord::file`]
+    pub line: Option<u32>,
+    /// Entered spans, from the outermost to the innermost, with their fields
+    pub spans: Vec<String>,
+    /// The log message
+    pub message: String,
+    /// Additional fields of the event as `(name, value)` pairs
+    pub fields: Vec<(String, String)>,
+    /// When the event was captured
+    pub timestamp: SystemTime,
+}
+
+impl LogRecord {
+    /// Formats the record the way the console displays it, styled with ANSI escape codes.
+    pub fn to_ansi_string(&self) -> String {
+        self.ansi_timestamp() + &self.ansi_body()
+    }
+
+    /// The dimmed timestamp prefix of [`LogRecord::to_ansi_string`].
+    pub(crate) fn ansi_timestamp(&self) -> String {
+        format!("\u{1b}[2m{}\u{1b}[0m ", format_timestamp(self.timestamp))
+    }
+
+    /// Everything following the timestamp in [`LogRecord::to_ansi_string`].
+    pub(crate) fn ansi_body(&self) -> String {
+        let level_color = match self.level {
+            Level::ERROR => 31,
+            Level::WARN => 33,
+            Level::INFO => 32,
+            Level::DEBUG => 34,
+            Level::TRACE => 35,
+        };
+        let mut line = format!("\u{1b}[{level_color}m{:>5}\u{1b}[0m ", self.level);
+        for span in &self.spans {
+            let _ = write!(line, "\u{1b}[1m{span}\u{1b}[0m: ");
+        }
+        line.push_str(&self.message);
+        for (name, value) in &self.fields {
+            let _ = write!(line, " \u{1b}[3m{name}\u{1b}[0m={value}");
+        }
+        line
+    }
+}
+
+/// Formats a timestamp as RFC 3339 in UTC, e.g. `2025-01-03T16:58:02.690280Z`.
+pub(crate) fn format_timestamp(timestamp: SystemTime) -> String {
+    let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:06}Z",
+        secs_of_day / 3_600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_micros()
+    )
+}
 
 /// Buffers logs written by bevy at runtime
+///
+/// Receiving end of a bounded channel, only locked by the console when flushing.
 #[derive(Resource)]
-pub struct BevyLogBuffer(Arc<Mutex<std::io::Cursor<Vec<u8>>>>);
+pub struct BevyLogBuffer {
+    receiver: Mutex<Receiver<LogRecord>>,
+    capacity: usize,
+    dropped: Arc<AtomicUsize>,
+}
 
-/// Writer implementation which writes into a buffer resource inside the bevy world
-pub struct BevyLogBufferWriter(Arc<Mutex<std::io::Cursor<Vec<u8>>>>);
+/// Tracing layer capturing events as [LogRecord]s into a [BevyLogBuffer]
+///
+/// Logging threads never block: when the buffer is full the newest record is dropped and counted.
+pub struct ConsoleLogLayer {
+    sender: SyncSender<LogRecord>,
+    dropped: Arc<AtomicUsize>,
+}
+
+/// Creates a connected layer and buffer holding at most `capacity` records, at least one.
+fn log_channel(capacity: usize) -> (ConsoleLogLayer, BevyLogBuffer) {
+    // A zero capacity channel only hands records over to a waiting receiver, dropping them all
+    let capacity = capacity.max(1);
+    let (sender, receiver) = mpsc::sync_channel(capacity);
+    let dropped = Arc::new(AtomicUsize::new(0));
+    (
+        ConsoleLogLayer {
+            sender,
+            dropped: dropped.clone(),
+        },
+        BevyLogBuffer {
+            receiver: Mutex::new(receiver),
+            capacity,
+            dropped,
+        },
+    )
+}
 
-impl Write for BevyLogBufferWriter {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        // let lock = self.0.upgrade().unwrap();
-        let mut lock = self.0.lock().map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to lock buffer: {}", e),
-            )
-        })?;
-        lock.write(buf)
+/// Formatted fields of a span, stored in the span's extensions
+struct SpanFields(String);
+
+impl<S> Layer<S> for ConsoleLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        span.extensions_mut()
+            .insert(SpanFields(visitor.joined_fields()));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        values.record(&mut visitor);
+        let mut extensions = span.extensions_mut();
+        match extensions.get_mut::<SpanFields>() {
+            Some(SpanFields(fields)) if !fields.is_empty() => {
+                fields.push(' ');
+                fields.push_str(&visitor.joined_fields());
+            }
+            Some(SpanFields(fields)) => *fields = visitor.joined_fields(),
+            None => extensions.insert(SpanFields(visitor.joined_fields())),
+        }
     }
 
-    fn flush(&mut self) -> std::io::Result<()> {
-        // let lock = self.0.upgrade().unwrap();
-        let mut lock = self.0.lock().map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to lock buffer: {}", e),
-            )
-        })?;
-        lock.flush()
+    fn on_event(&self, event: &TracingEvent<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| {
+                scope
+                    .from_root()
+                    .map(|span| {
+                        let extensions = span.extensions();
+                        match extensions.get::<SpanFields>() {
+                            Some(SpanFields(fields)) if !fields.is_empty() => {
+                                format!("{}{{{fields}}}", span.name())
+                            }
+                            _ => span.name().to_string(),
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let record = LogRecord {
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            module_path: metadata.module_path().map(str::to_string),
+            file: metadata.file().map(str::to_string),
+            line: metadata.line(),
+            spans,
+            message: visitor.message,
+            fields: visitor.fields,
+            timestamp: SystemTime::now(),
+        };
+
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(record) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Collects the message and the remaining fields of an event or span
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+impl FieldVisitor {
+    fn joined_fields(&self) -> String {
+        self.fields
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields
+                .push((field.name().to_string(), value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.fields
+                .push((field.name().to_string(), format!("{value:?}")));
+        }
+    }
+}
+
+/// Filter of all log output, which can be replaced at runtime.
+///
+/// Only available when logs are captured with [make_layer] or [make_filtered_layer]. The
+/// directives apply to every log layer, not only to the console. The `LogPlugin` filter still
+/// applies as well, set it to let everything through so the console filter decides:
+///
+/// ```ignore
+/// DefaultPlugins.set(LogPlugin {
+///     level: log::Level::TRACE,
+///     filter: String::new(),
+///     custom_layer: make_layer,
+/// })
+/// ```
+#[derive(Resource)]
+pub struct ConsoleLogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    directives: String,
+}
+
+impl ConsoleLogFilter {
+    fn new(directives: String) -> (reload::Layer<EnvFilter, Registry>, Self) {
+        let (filter, handle) = reload::Layer::new(EnvFilter::builder().parse_lossy(&directives));
+        (filter, Self { handle, directives })
+    }
+
+    /// Returns the currently active [EnvFilter] directives.
+    pub fn directives(&self) -> &str {
+        &self.directives
+    }
+
+    /// Replaces all directives, e.g. with `"mygame=debug,warn"`.
+    pub fn set_directives(&mut self, directives: impl Into<String>) -> Result<(), ParseError> {
+        let directives = directives.into();
+        let filter = EnvFilter::builder().parse(&directives)?;
+        if let Err(err) = self.handle.reload(filter) {
+            warn!("Failed to reload console log filter: {err}");
+        }
+        self.directives = directives;
+        Ok(())
+    }
+
+    /// Sets the level of a single target, replacing any previous directive for it.
+    pub fn set_level(&mut self, target: &str, level: &str) -> Result<(), ParseError> {
+        let mut directives = self
+            .directives
+            .split(',')
+            .map(str::trim)
+            .filter(|directive| !directive.is_empty() && directive_target(directive) != target)
+            .collect::<Vec<_>>();
+        let new_directive = format!("{target}={level}");
+        directives.push(&new_directive);
+        self.set_directives(directives.join(","))
+    }
+}
+
+/// Returns the target a directive applies to, or the whole directive if it is a bare level/target.
+fn directive_target(directive: &str) -> &str {
+    match directive.find('=') {
+        Some(end) => {
+            let target = &directive[..end];
+            target.split('[').next().unwrap_or(target)
+        }
+        None => directive,
     }
 }
 
 /// Flushes the log buffer and sends its content to the console
 pub fn send_log_buffer_to_console(
-    buffer: ResMut<BevyLogBuffer>,
-    mut console_lines: EventWriter<PrintConsoleLine>,
+    buffer: Res<BevyLogBuffer>,
+    mut log_records: EventWriter<LogRecord>,
 ) {
-    let mut buffer = buffer.0.lock().unwrap();
-    // read and clean buffer
-    let buffer = buffer.get_mut();
-    for line in buffer.lines().map_while(Result::ok) {
-        console_lines.write(PrintConsoleLine { line });
+    // A panic elsewhere while holding the lock leaves the receiver itself intact
+    let receiver = buffer
+        .receiver
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    log_records.write_batch(receiver.try_iter().take(buffer.capacity));
+
+    let dropped = buffer.dropped.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        log_records.write(LogRecord {
+            level: Level::WARN,
+            target: module_path!().to_string(),
+            module_path: Some(module_path!().to_string()),
+            file: None,
+            line: None,
+            spans: Vec::new(),
+            message: format!("{dropped} log messages dropped, the console log buffer was full"),
+            fields: Vec::new(),
+            timestamp: SystemTime::now(),
+        });
     }
-    buffer.clear();
 }
 
 /// Creates a tracing layer which writes logs into a buffer resource inside the bevy world
 /// This is used by the console plugin to capture logs written by bevy
+/// Logs are filtered with [DEFAULT_CONSOLE_FILTER], see [ConsoleLogFilter].
 /// Use [make_filtered_layer] for more customization options.
 pub fn make_layer(
     app: &mut App,
 ) -> Option<Box<dyn tracing_subscriber::Layer<Registry> + Send + Sync>> {
-    setup_layer(app, None)
+    setup_layer(
+        app,
+        DEFAULT_CONSOLE_FILTER.to_string(),
+        DEFAULT_LOG_BUFFER_CAPACITY,
+    )
 }
 
 /// Creates a tracing layer which writes logs into a buffer resource inside the bevy world
-/// Uses a custom [EnvFilter] string, allowing for a different subset of log entries to be
-/// captured by the console.
+/// Uses a custom [EnvFilter] string, which filters all log output.
 /// This is used by the console plugin to capture logs written by bevy
+/// The filter can be changed at runtime through [ConsoleLogFilter] or the `log_filter` command.
 ///
 /// ## Example
 /// ```ignore
 /// DefaultPlugins.set(LogPlugin {
-///    filter: log::DEFAULT_FILTER.to_string(),
-///    level: log::Level::INFO,
+///    filter: String::new(),
+///    level: log::Level::TRACE,
 ///    custom_layer: |app: &mut App| make_filtered_layer(app,
-///        "mygame=info,warn,debug,error".to_string())
+///        "mygame=debug,info".to_string())
 ///})
 ///```
 pub fn make_filtered_layer(
     app: &mut App,
     filter: String,
 ) -> Option<Box<dyn tracing_subscriber::Layer<Registry> + Send + Sync>> {
-    let env_filter = EnvFilter::builder().parse_lossy(filter);
-    setup_layer(app, Some(env_filter))
+    setup_layer(app, filter, DEFAULT_LOG_BUFFER_CAPACITY)
+}
+
+/// Same as [make_filtered_layer], buffering at most `capacity` records between two console
+/// updates instead of [DEFAULT_LOG_BUFFER_CAPACITY].
+///
+/// Records logged while the buffer is full, e.g. during a log storm while loading, are dropped
+/// and reported with a single warning once the console catches up. A `capacity` of zero is
+/// raised to one.
+pub fn make_bounded_layer(
+    app: &mut App,
+    filter: String,
+    capacity: usize,
+) -> Option<Box<dyn tracing_subscriber::Layer<Registry> + Send + Sync>> {
+    setup_layer(app, filter, capacity)
 }
 
 /// Performs common layer setup
 fn setup_layer(
     app: &mut App,
-    filter: Option<EnvFilter>,
+    directives: String,
+    capacity: usize,
 ) -> Option<Box<dyn tracing_subscriber::Layer<Registry> + Send + Sync>> {
-    let buffer = Arc::new(Mutex::new(std::io::Cursor::new(Vec::new())));
-    app.insert_resource(BevyLogBuffer(buffer.clone()));
+    let (layer, buffer) = log_channel(capacity);
+    app.insert_resource(buffer);
     app.add_systems(
         Update,
         send_log_buffer_to_console.in_set(ConsoleSet::PostCommands),
     );
 
-    let layer: Box<dyn tracing_subscriber::Layer<Registry> + Send + Sync> = match filter {
-        Some(filter) => Box::new(
-            tracing_subscriber::fmt::Layer::new()
-                .with_target(false)
-                .with_ansi(true)
-                .with_writer(move || BevyLogBufferWriter(buffer.clone()))
-                .with_filter(filter),
-        ),
-        None => Box::new(
-            tracing_subscriber::fmt::Layer::new()
-                .with_target(false)
-                .with_ansi(true)
-                .with_writer(move || BevyLogBufferWriter(buffer.clone())),
-        ),
-    };
-
-    Some(layer)
+    // A global filter, a per-layer one could only narrow down what the `LogPlugin` lets through
+    let (filter, log_filter) = ConsoleLogFilter::new(directives);
+    app.insert_resource(log_filter);
+    app.add_console_command::<LogFilterCommand, _>(log_filter_command)
+        .add_console_command::<LogLevelCommand, _>(log_level_command);
+
+    Some(Box::new(filter.and_then(layer)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::log::{debug, info, tracing, tracing_subscriber::layer::SubscriberExt};
+
+    use super::*;
+
+    #[test]
+    fn test_full_buffer_drops_newest_records() {
+        let (layer, buffer) = log_channel(2);
+        let subscriber = Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 0..5 {
+                info!("message {i}");
+            }
+        });
+
+        let receiver = buffer.receiver.lock().unwrap();
+        let messages = receiver
+            .try_iter()
+            .map(|record| record.message)
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec!["message 0", "message 1"]);
+        assert_eq!(buffer.dropped.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_zero_capacity_keeps_one_record() {
+        let (layer, buffer) = log_channel(0);
+        let subscriber = Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            info!("first");
+            info!("second");
+        });
+
+        let receiver = buffer.receiver.lock().unwrap();
+        let messages = receiver
+            .try_iter()
+            .map(|record| record.message)
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec!["first"]);
+        assert_eq!(buffer.capacity, 1);
+    }
+
+    #[test]
+    fn test_filter_applies_to_all_layers() {
+        let (filter, mut log_filter) = ConsoleLogFilter::new("info".to_string());
+        let (layer, buffer) = log_channel(16);
+        let (other_layer, other_buffer) = log_channel(16);
+        let subscriber = Registry::default()
+            .with(filter.and_then(layer))
+            .with(other_layer);
+        tracing::subscriber::with_default(subscriber, || {
+            debug!("hidden");
+            log_filter.set_level(module_path!(), "debug").unwrap();
+            debug!("shown");
+        });
+
+        for buffer in [buffer, other_buffer] {
+            let receiver = buffer.receiver.lock().unwrap();
+            let messages = receiver
+                .try_iter()
+                .map(|record| record.message)
+                .collect::<Vec<_>>();
+            assert_eq!(messages, vec!["shown"]);
+        }
+        assert_eq!(
+            log_filter.directives(),
+            "info,bevy_console::log::tests=debug"
+        );
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        let timestamp = UNIX_EPOCH + Duration::from_micros(1_735_923_482_690_280);
+        assert_eq!(format_timestamp(timestamp), "2025-01-03T16:58:02.690280Z");
+    }
+
+    #[test]
+    fn test_format_timestamp_leap_day() {
+        let timestamp = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(format_timestamp(timestamp), "2000-02-29T00:00:00.000000Z");
+    }
+
+    #[test]
+    fn test_multiline_message_is_kept_intact() {
+        let record = LogRecord {
+            level: Level::WARN,
+            target: "mygame".to_string(),
+            module_path: None,
+            file: None,
+            line: None,
+            spans: vec!["update{frame=1}".to_string()],
+            message: "first\nsecond".to_string(),
+            fields: vec![("id".to_string(), "4".to_string())],
+            timestamp: UNIX_EPOCH,
+        };
+
+        let line = strip_ansi_escapes::strip_str(record.to_ansi_string());
+        assert_eq!(
+            line,
+            "1970-01-01T00:00:00.000000Z  WARN update{frame=1}: first\nsecond id=4"
+        );
+    }
 }
@@ -0,0 +1,379 @@
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use clap::Parser;
+use regex::{Regex, RegexBuilder};
+use shlex::Shlex;
+
+use crate::color::plain_text;
+
+/// Filter built-ins which can follow a command after `|`
+#[derive(Parser, Debug, PartialEq)]
+#[command(name = "|", no_binary_name = true)]
+enum PipeFilterCommand {
+    /// Keeps lines matching a regular expression
+    Grep {
+        /// Match case-insensitively
+        #[arg(short, long)]
+        ignore_case: bool,
+        /// Keep the lines which do not match instead
+        #[arg(short = 'v', long)]
+        invert_match: bool,
+        /// Regular expression, matched against the text without styling
+        pattern: String,
+    },
+    /// Keeps the first lines
+    Head {
+        /// Number of lines to keep
+        #[arg(default_value_t = 10)]
+        lines: usize,
+    },
+    /// Keeps the last lines
+    Tail {
+        /// Number of lines to keep
+        #[arg(default_value_t = 10)]
+        lines: usize,
+    },
+    /// Replaces the lines with their count
+    Count,
+}
+
+#[derive(Debug)]
+enum PipeFilter {
+    Grep { regex: Regex, invert: bool },
+    Head(usize),
+    Tail(usize),
+    Count,
+}
+
+impl PipeFilter {
+    fn parse(stage: &str) -> Result<Self, String> {
+        let words = Shlex::new(stage).collect::<Vec<_>>();
+        if words.is_empty() {
+            return Err("error: Empty command in pipeline".to_string());
+        }
+
+        let command = PipeFilterCommand::try_parse_from(words).map_err(|err| err.to_string())?;
+        Ok(match command {
+            PipeFilterCommand::Grep {
+                ignore_case,
+                invert_match,
+                pattern,
+            } => PipeFilter::Grep {
+                regex: RegexBuilder::new(&pattern)
+                    .case_insensitive(ignore_case)
+                    .build()
+                    .map_err(|err| format!("error: {err}"))?,
+                invert: invert_match,
+            },
+            PipeFilterCommand::Head { lines } => PipeFilter::Head(lines),
+            PipeFilterCommand::Tail { lines } => PipeFilter::Tail(lines),
+            PipeFilterCommand::Count => PipeFilter::Count,
+        })
+    }
+
+    fn apply(&self, mut lines: Vec<String>) -> Vec<String> {
+        match self {
+            PipeFilter::Grep { regex, invert } => {
+                lines.retain(|line| regex.is_match(&plain_text(line)) != *invert);
+            }
+            PipeFilter::Head(count) => lines.truncate(*count),
+            PipeFilter::Tail(count) => {
+                lines.drain(..lines.len().saturating_sub(*count));
+            }
+            PipeFilter::Count => lines = vec![lines.len().to_string()],
+        }
+        lines
+    }
+}
+
+/// File receiving the output of a command entered with `>` or `>>`
+#[derive(Debug, PartialEq)]
+struct Redirect {
+    path: PathBuf,
+    append: bool,
+}
+
+impl Redirect {
+    fn parse(target: &str, append: bool) -> Result<Self, String> {
+        let mut words = Shlex::new(target);
+        match (words.next(), words.next()) {
+            (Some(path), None) => Ok(Self {
+                path: path.into(),
+                append,
+            }),
+            _ => Err("error: Expected a single file name after `>`".to_string()),
+        }
+    }
+
+    fn write(&self, lines: &[String]) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(self.append)
+            .truncate(!self.append)
+            .open(&self.path)?;
+        let mut file = BufWriter::new(file);
+        for line in lines {
+            writeln!(file, "{}", strip_ansi_escapes::strip_str(line))?;
+        }
+        file.flush()
+    }
+}
+
+/// Output of a command entered with `|`, `>` or `>>`, held back until the command had a chance
+/// to reply.
+#[derive(Debug)]
+pub(crate) struct Pipeline {
+    filters: Vec<PipeFilter>,
+    redirect: Option<Redirect>,
+    lines: Vec<String>,
+    /// `[ok]`, `[failed]` and `[cancelled]` lines, which are shown after the filtered output
+    status: Vec<String>,
+    frames: u32,
+}
+
+impl Pipeline {
+    /// Splits a command line into the command and the pipeline its output goes through.
+    ///
+    /// The pipeline is `None` if the command line has neither `|` nor a redirect. Only a
+    /// standalone `>` or `>>` is a redirect, so `echo a>b` passes `a>b` to the command.
+    pub(crate) fn parse(line: &str) -> Result<(&str, Option<Self>), String> {
+        let (line, redirect) = match find_redirect(line) {
+            Some((i, append)) => {
+                let target = &line[i + 1 + usize::from(append)..];
+                (&line[..i], Some(Redirect::parse(target, append)?))
+            }
+            None => (line, None),
+        };
+
+        let mut stages = split_pipeline(line).into_iter();
+        let command = stages.next().unwrap_or_default();
+        let filters = stages
+            .map(PipeFilter::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if filters.is_empty() && redirect.is_none() {
+            return Ok((command, None));
+        }
+
+        Ok((
+            command,
+            Some(Self {
+                filters,
+                redirect,
+                lines: Vec::new(),
+                status: Vec::new(),
+                frames: 0,
+            }),
+        ))
+    }
+
+    /// Captures a reply of the command.
+    pub(crate) fn capture(&mut self, reply: &str) {
+        if matches!(reply, "[ok]" | "[failed]" | "[cancelled]") {
+            self.status.push(reply.to_string());
+        } else {
+            self.lines.extend(reply.split('\n').map(str::to_string));
+        }
+    }
+
+    /// Called once per frame, returns `true` once the command systems ran since it was entered.
+    pub(crate) fn tick(&mut self) -> bool {
+        self.frames += 1;
+        self.frames > 1
+    }
+
+    /// Runs the captured output through the filters, writing it to the redirect file if any.
+    ///
+    /// Returns the lines to show in the scrollback.
+    pub(crate) fn finish(self) -> Vec<String> {
+        let mut lines = self
+            .filters
+            .iter()
+            .fold(self.lines, |lines, filter| filter.apply(lines));
+        if let Some(redirect) = &self.redirect {
+            lines = match redirect.write(&lines) {
+                Ok(()) => Vec::new(),
+                Err(err) => vec![format!(
+                    "error: Failed to write {}: {err}",
+                    redirect.path.display()
+                )],
+            };
+        }
+        lines.extend(self.status);
+        lines
+    }
+}
+
+/// Byte offsets of the characters in `line` which are not quoted or escaped.
+fn unquoted(line: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut quote = None;
+    let mut escaped = false;
+    line.char_indices().filter(move |&(_, c)| {
+        match (c, quote) {
+            _ if escaped => escaped = false,
+            ('\\', Some('"') | None) => escaped = true,
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (_, None) => return true,
+            _ => {}
+        }
+        false
+    })
+}
+
+/// Finds the first unquoted `>` or `>>` surrounded by whitespace, returning its byte offset
+/// and whether it appends.
+fn find_redirect(line: &str) -> Option<(usize, bool)> {
+    let standalone = |i: usize, len: usize| {
+        let before = line[..i].chars().next_back();
+        let after = line[i + len..].chars().next();
+        before.is_none_or(char::is_whitespace) && after.is_none_or(char::is_whitespace)
+    };
+    unquoted(line)
+        .filter(|(_, c)| *c == '>')
+        .find_map(|(i, _)| {
+            if standalone(i, 1) {
+                Some((i, false))
+            } else if line[i + 1..].starts_with('>') && standalone(i, 2) {
+                Some((i, true))
+            } else {
+                None
+            }
+        })
+}
+
+/// Splits a command line at each `|` which is not quoted or escaped.
+fn split_pipeline(line: &str) -> Vec<&str> {
+    let mut stages = Vec::new();
+    let mut start = 0;
+    for (i, _) in unquoted(line).filter(|(_, c)| *c == '|') {
+        stages.push(&line[start..i]);
+        start = i + 1;
+    }
+    stages.push(&line[start..]);
+    stages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(line: &str, output: &[&str]) -> Vec<String> {
+        let (_, pipeline) = Pipeline::parse(line).unwrap();
+        let mut pipeline = pipeline.unwrap();
+        for line in output {
+            pipeline.capture(line);
+        }
+        pipeline.finish()
+    }
+
+    #[test]
+    fn test_split_pipeline() {
+        assert_eq!(split_pipeline("help"), vec!["help"]);
+        assert_eq!(
+            split_pipeline("help | grep spawn | count"),
+            vec!["help ", " grep spawn ", " count"]
+        );
+    }
+
+    #[test]
+    fn test_split_pipeline_ignores_quoted_bars() {
+        assert_eq!(
+            split_pipeline(r#"echo "a|b" 'c|d' e\|f | count"#),
+            vec![r#"echo "a|b" 'c|d' e\|f "#, " count"]
+        );
+    }
+
+    #[test]
+    fn test_grep() {
+        let output = ["spawn: Spawns things", "\u{1b}[1mSpawner\u{1b}[0m", "exit"];
+        assert_eq!(
+            run("help | grep spawn", &output),
+            vec!["spawn: Spawns things"]
+        );
+        assert_eq!(run("help | grep -i ^spawn", &output).len(), 2);
+        assert_eq!(run("help | grep -v spawn", &output).len(), 2);
+    }
+
+    #[test]
+    fn test_multiline_replies_are_split() {
+        assert_eq!(run("help | tail 2", &["a\nb\nc"]), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn test_chained_filters_keep_status_last() {
+        let output = ["1", "2", "3", "[ok]"];
+        assert_eq!(run("help | head 2 | count", &output), vec!["2", "[ok]"]);
+    }
+
+    #[test]
+    fn test_invalid_filters() {
+        assert!(Pipeline::parse("help | sort").is_err());
+        assert!(Pipeline::parse("help | ").is_err());
+        assert!(Pipeline::parse("help | grep (").is_err());
+        assert!(Pipeline::parse("help >").is_err());
+        assert!(Pipeline::parse("help > a b").is_err());
+    }
+
+    #[test]
+    fn test_plain_command_has_no_pipeline() {
+        let (command, pipeline) = Pipeline::parse(r#"echo "a | b > c""#).unwrap();
+        assert_eq!(command, r#"echo "a | b > c""#);
+        assert!(pipeline.is_none());
+    }
+
+    #[test]
+    fn test_greater_than_inside_argument_is_not_a_redirect() {
+        for line in [
+            "echo a>b",
+            "echo a>>b",
+            "echo >b",
+            "echo a> b",
+            "echo a >>> b",
+        ] {
+            let (command, pipeline) = Pipeline::parse(line).unwrap();
+            assert_eq!(command, line);
+            assert!(pipeline.is_none(), "{line}");
+        }
+        let (command, pipeline) = Pipeline::parse("echo a>b > out.txt").unwrap();
+        assert_eq!(command, "echo a>b ");
+        assert_eq!(
+            pipeline.unwrap().redirect.unwrap().path,
+            PathBuf::from("out.txt")
+        );
+    }
+
+    #[test]
+    fn test_parse_redirect() {
+        let (command, pipeline) = Pipeline::parse("help | head 1 >> 'out file.txt'").unwrap();
+        assert_eq!(command, "help ");
+        let pipeline = pipeline.unwrap();
+        assert_eq!(pipeline.filters.len(), 1);
+        assert_eq!(
+            pipeline.redirect,
+            Some(Redirect {
+                path: "out file.txt".into(),
+                append: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_redirect_writes_plain_text() {
+        let path = std::env::temp_dir().join(format!(
+            "bevy_console_redirect_test_{}.txt",
+            std::process::id()
+        ));
+        let line = format!("help > {}", path.display());
+
+        let output = run(&line, &["\u{1b}[1mhelp\u{1b}[0m", "[ok]"]);
+        assert_eq!(output, vec!["[ok]"]);
+        run(&line.replace('>', ">>"), &["exit"]);
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(written, "help\nexit\n");
+    }
+}
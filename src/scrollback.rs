@@ -0,0 +1,1463 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::mem;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use ansi_parser::AnsiSequence;
+use bevy::log::{warn, Level};
+use bevy_egui::egui::Galley;
+
+use crate::color::{has_cursor_controls, leading_cursor_controls, tokenize, Token};
+use crate::log::format_timestamp;
+use crate::{ConsoleConfiguration, InvocationId, LogRecord};
+
+/// Default value of [`ConsoleConfiguration::scrollback_size`]
+pub(crate) const DEFAULT_SCROLLBACK_SIZE: usize = 10_000;
+
+/// Levels in the order they are shown in the console, most severe first
+pub(crate) const LEVELS: [Level; 5] = [
+    Level::ERROR,
+    Level::WARN,
+    Level::INFO,
+    Level::DEBUG,
+    Level::TRACE,
+];
+
+/// Channel of echoed input and command replies printed without a channel
+pub(crate) const COMMANDS_CHANNEL: &str = "commands";
+/// Channel of captured log records
+pub(crate) const LOGS_CHANNEL: &str = "logs";
+
+/// Where a scrollback line came from
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum LineKind {
+    /// Echoed input and command replies, with the command which printed them
+    Output {
+        invocation: Option<InvocationId>,
+        /// Channel the line was printed to, [`COMMANDS_CHANNEL`] if `None`
+        channel: Option<String>,
+    },
+    /// Captured log record
+    Log { level: Level, target: String },
+}
+
+/// Outcome of a command, shown on the header of its block
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BlockStatus {
+    Ok,
+    Failed,
+    Cancelled,
+}
+
+impl BlockStatus {
+    /// Status of a reply printed by [`ConsoleCommand::ok`](crate::ConsoleCommand::ok) and the like
+    fn from_reply(reply: &str) -> Option<Self> {
+        match reply {
+            "[ok]" => Some(Self::Ok),
+            "[failed]" => Some(Self::Failed),
+            "[cancelled]" => Some(Self::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+/// Echoed command line, starting the block of the replies to the command
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct BlockHeader {
+    pub(crate) status: Option<BlockStatus>,
+    /// The replies are hidden
+    pub(crate) folded: bool,
+    /// Number of reply entries
+    pub(crate) replies: usize,
+}
+
+/// When a scrollback entry arrived
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct LineStamp {
+    pub(crate) wall: SystemTime,
+    /// [`Time::elapsed`](bevy::time::Time::elapsed) of the frame
+    pub(crate) elapsed: Duration,
+    pub(crate) frame: u32,
+}
+
+impl LineStamp {
+    /// Time of day in UTC, elapsed seconds and frame number, padded to line up in a column.
+    pub(crate) fn format(&self) -> String {
+        let time_of_day = &format_timestamp(self.wall)[11..23];
+        format!(
+            "{time_of_day} {:>9.3}s #{:<7}",
+            self.elapsed.as_secs_f64(),
+            self.frame
+        )
+    }
+}
+
+/// A single entry of the scrollback
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ScrollbackLine {
+    /// Text to display, may contain ANSI escape codes and newlines
+    pub(crate) text: String,
+    pub(crate) kind: LineKind,
+    /// How many consecutive identical lines this entry stands for
+    pub(crate) repeat: usize,
+    /// Byte offset of the part of `text` compared when collapsing, skipping log timestamps
+    body_start: usize,
+    /// Number of rows the line takes up, lines are never wrapped
+    rows: usize,
+    /// Layout of [`Self::display_text`], created the first time the line is shown
+    layout: Option<Arc<Galley>>,
+    /// Removed through its line handle, kept so the ids of the following entries don't change
+    removed: bool,
+    /// When the entry arrived, if recorded
+    pub(crate) stamp: Option<LineStamp>,
+    /// Set on the echoed line of a command
+    pub(crate) header: Option<BlockHeader>,
+}
+
+impl ScrollbackLine {
+    fn new(text: String, kind: LineKind, body_start: usize) -> Self {
+        Self {
+            rows: rows(&text),
+            text,
+            kind,
+            repeat: 1,
+            body_start,
+            layout: None,
+            removed: false,
+            stamp: None,
+            header: None,
+        }
+    }
+
+    /// Changes the text in place, e.g. when overwritten after a carriage return.
+    ///
+    /// The entry keeps its kind and the start of its body.
+    fn set_text(&mut self, text: String) {
+        self.rows = rows(&text);
+        self.text = text;
+        self.repeat = 1;
+        self.layout = None;
+        self.removed = false;
+    }
+
+    fn body(&self) -> &str {
+        // All of the text while a rewrite is shorter than the prefix
+        self.text.get(self.body_start..).unwrap_or(&self.text)
+    }
+
+    fn is_repeat_of(&self, other: &ScrollbackLine) -> bool {
+        !self.body().is_empty()
+            && !other.removed
+            && other.header.is_none()
+            && self.kind == other.kind
+            && self.body() == other.body()
+    }
+
+    /// Channel the line was printed to.
+    pub(crate) fn channel(&self) -> &str {
+        match &self.kind {
+            LineKind::Output {
+                channel: Some(channel),
+                ..
+            } => channel,
+            LineKind::Output { channel: None, .. } => COMMANDS_CHANNEL,
+            LineKind::Log { .. } => LOGS_CHANNEL,
+        }
+    }
+
+    /// Text to display, including the repeat counter of collapsed lines.
+    pub(crate) fn display_text(&self) -> Cow<'_, str> {
+        if self.repeat > 1 {
+            Cow::Owned(format!(
+                "{} \u{1b}[2m(x{})\u{1b}[0m",
+                self.text, self.repeat
+            ))
+        } else {
+            Cow::Borrowed(&self.text)
+        }
+    }
+
+    /// Display text without ANSI styling, as written to files.
+    pub(crate) fn unstyled_text(&self) -> String {
+        strip_ansi_escapes::strip_str(self.display_text())
+    }
+
+    /// Returns the cached layout, calling `layout` with the display text if there is none yet.
+    pub(crate) fn layout(&mut self, layout: impl FnOnce(&str) -> Arc<Galley>) -> Arc<Galley> {
+        match &self.layout {
+            Some(galley) => galley.clone(),
+            None => {
+                let galley = layout(&self.display_text());
+                self.layout = Some(galley.clone());
+                galley
+            }
+        }
+    }
+}
+
+fn rows(text: &str) -> usize {
+    text.matches('\n').count() + 1
+}
+
+/// All lines printed to the console.
+///
+/// Lines are kept regardless of the active [`ScrollbackFilter`], so filters can be changed after
+/// the fact.
+///
+/// Text containing carriage returns or cursor controls (`ESC[nA`, `ESC[2K`, ...) is applied to the
+/// most recent entries like a terminal would, so progress output can update in place. Each entry
+/// counts as one line for cursor movement, and text written at the start of a line replaces it.
+pub(crate) struct Scrollback {
+    lines: VecDeque<ScrollbackLine>,
+    /// Id of the front entry, every appended entry gets the next id
+    first_id: u64,
+    /// Lowest id of the entries changed in place since [`Self::take_modified`] was last called
+    modified_from: Option<u64>,
+    /// Stamp of the entries appended in the current frame, if recording stamps
+    stamp: Option<LineStamp>,
+    /// Ids of the block headers of the commands
+    blocks: BTreeMap<InvocationId, u64>,
+    /// Channels lines were printed to, kept when the lines are evicted or cleared
+    channels: Vec<String>,
+    has_logs: bool,
+    /// Merge consecutive identical lines into one entry with a repeat counter
+    pub(crate) collapse_repeated: bool,
+    /// Maximum number of entries, older ones are evicted first
+    pub(crate) capacity: usize,
+    spill: Option<SpillFile>,
+}
+
+impl Default for Scrollback {
+    fn default() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            first_id: 0,
+            modified_from: None,
+            stamp: None,
+            blocks: BTreeMap::new(),
+            channels: Vec::new(),
+            has_logs: false,
+            collapse_repeated: false,
+            capacity: DEFAULT_SCROLLBACK_SIZE,
+            spill: None,
+        }
+    }
+}
+
+impl Scrollback {
+    /// Applies the scrollback related settings of the configuration.
+    pub(crate) fn configure(&mut self, config: &ConsoleConfiguration) {
+        self.collapse_repeated = config.collapse_repeated_lines;
+        // Without room for a single entry every line would be evicted as soon as it is pushed
+        self.capacity = config.scrollback_size.max(1);
+        if self.spill.as_ref().map(|spill| &spill.path) != config.scrollback_spill_path.as_ref() {
+            self.spill = config.scrollback_spill_path.clone().map(SpillFile::new);
+        }
+        self.evict();
+    }
+
+    /// Sets the stamp of the entries appended from now on, `None` to stop recording stamps.
+    pub(crate) fn set_stamp(&mut self, stamp: Option<LineStamp>) {
+        self.stamp = stamp;
+    }
+
+    /// Appends input or command output.
+    pub(crate) fn push_output(&mut self, text: impl Into<String>) {
+        self.push_reply(text, None);
+    }
+
+    /// Appends an echoed command line, starting the block of its replies.
+    pub(crate) fn push_command(&mut self, text: impl Into<String>, invocation: InvocationId) {
+        let mut line = ScrollbackLine::new(
+            text.into(),
+            LineKind::Output {
+                invocation: Some(invocation),
+                channel: None,
+            },
+            0,
+        );
+        line.header = Some(BlockHeader::default());
+        self.blocks.insert(invocation, self.next_id());
+        self.push_back(line);
+    }
+
+    /// Appends a reply of a command, `[ok]`, `[failed]` and `[cancelled]` also set its status.
+    pub(crate) fn push_reply(&mut self, text: impl Into<String>, invocation: Option<InvocationId>) {
+        self.push_to_channel(text, invocation, None);
+    }
+
+    /// Appends output printed to a channel, or to [`COMMANDS_CHANNEL`] if `None`.
+    pub(crate) fn push_to_channel(
+        &mut self,
+        text: impl Into<String>,
+        invocation: Option<InvocationId>,
+        channel: Option<String>,
+    ) {
+        let text = text.into();
+        if let (Some(invocation), Some(status)) = (invocation, BlockStatus::from_reply(&text)) {
+            self.set_block_status(invocation, status);
+        }
+        self.write(
+            text,
+            LineKind::Output {
+                invocation,
+                channel,
+            },
+            0,
+        );
+    }
+
+    pub(crate) fn set_block_status(&mut self, invocation: InvocationId, status: BlockStatus) {
+        if let Some(header) = self.block_header(invocation) {
+            header.status = Some(status);
+        }
+    }
+
+    /// Shows or hides the replies of a command.
+    pub(crate) fn toggle_fold(&mut self, invocation: InvocationId) {
+        let Some(header) = self.block_header(invocation) else {
+            return;
+        };
+        header.folded = !header.folded;
+        if let Some(&id) = self.blocks.get(&invocation) {
+            self.mark_modified(id);
+        }
+    }
+
+    fn block_header(&mut self, invocation: InvocationId) -> Option<&mut BlockHeader> {
+        let id = *self.blocks.get(&invocation)?;
+        self.get_mut(id)?.header.as_mut()
+    }
+
+    /// Whether the entry is a reply in a folded block.
+    fn is_folded(&self, line: &ScrollbackLine) -> bool {
+        match line.kind {
+            LineKind::Output {
+                invocation: Some(invocation),
+                ..
+            } if line.header.is_none() => self
+                .blocks
+                .get(&invocation)
+                .and_then(|id| self.get(*id)?.header.as_ref())
+                .is_some_and(|header| header.folded),
+            _ => false,
+        }
+    }
+
+    /// Appends a captured log record.
+    pub(crate) fn push_log(&mut self, record: &LogRecord) {
+        self.has_logs = true;
+        let kind = LineKind::Log {
+            level: record.level,
+            target: record.target.clone(),
+        };
+
+        // Cursor controls leading the message move the whole line, including timestamp and level
+        let controls = leading_cursor_controls(&record.message);
+        if controls > 0 {
+            let (controls, message) = record.message.split_at(controls);
+            let record = LogRecord {
+                message: message.to_string(),
+                ..record.clone()
+            };
+            let text = format!("{controls}{}", record.to_ansi_string());
+            self.write(text, kind, 0);
+        } else {
+            let timestamp = record.ansi_timestamp();
+            let body_start = timestamp.len();
+            self.write(timestamp + &record.ansi_body(), kind, body_start);
+        }
+    }
+
+    fn write(&mut self, mut text: String, kind: LineKind, body_start: usize) {
+        // Only a bare `\r` returns the cursor, `\r\n` is an ordinary newline
+        if text.contains("\r\n") {
+            text = text.replace("\r\n", "\n");
+        }
+        if has_cursor_controls(&text) {
+            self.write_with_cursor(&text, &kind);
+        } else {
+            self.push(ScrollbackLine::new(text, kind, body_start));
+        }
+    }
+
+    /// Writes text like a terminal whose cursor is at the start of a new line.
+    ///
+    /// Text starting with `\r` continues the last entry instead, like output which was written
+    /// without a trailing newline.
+    fn write_with_cursor(&mut self, text: &str, kind: &LineKind) {
+        let mut id = if text.starts_with('\r') && !self.lines.is_empty() {
+            self.next_id() - 1
+        } else {
+            self.next_id()
+        };
+        let mut line_start = true;
+
+        for token in tokenize(text) {
+            match token {
+                Token::Text(run) => {
+                    for (i, line) in run.split('\n').enumerate() {
+                        if i > 0 {
+                            if id >= self.next_id() {
+                                self.write_at(id, "", false, kind);
+                            }
+                            id += 1;
+                            line_start = true;
+                        }
+                        for (j, part) in line.split('\r').enumerate() {
+                            line_start |= j > 0;
+                            if !part.is_empty() {
+                                self.write_at(id, part, line_start, kind);
+                                line_start = false;
+                            }
+                        }
+                    }
+                }
+                Token::Escape(escape) => match escape {
+                    AnsiSequence::CursorUp(count) => {
+                        id = id
+                            .saturating_sub(u64::from(count.max(1)))
+                            .max(self.first_id);
+                    }
+                    AnsiSequence::CursorDown(count) => {
+                        id = (id + u64::from(count.max(1))).min(self.next_id());
+                    }
+                    AnsiSequence::EraseLine if line_start => self.write_at(id, "", true, kind),
+                    _ => {}
+                },
+                Token::Csi { params, final_byte } => {
+                    let count = params.parse::<u64>().unwrap_or(1).max(1);
+                    match final_byte {
+                        'A' | 'F' => id = id.saturating_sub(count).max(self.first_id),
+                        'B' | 'E' => id = (id + count).min(self.next_id()),
+                        'K' => {
+                            let erases_line = match params {
+                                "2" => true,
+                                "1" => !line_start,
+                                _ => line_start,
+                            };
+                            if erases_line {
+                                self.write_at(id, "", true, kind);
+                            }
+                        }
+                        'm' => {
+                            let sgr = format!("\u{1b}[{params}m");
+                            self.write_at(id, &sgr, line_start, kind);
+                            line_start = false;
+                        }
+                        _ => {}
+                    }
+                    line_start |= matches!(final_byte, 'E' | 'F');
+                }
+                Token::Link(uri) => {
+                    let link = format!("\u{1b}]8;;{uri}\u{1b}\\");
+                    self.write_at(id, &link, line_start, kind);
+                    line_start = false;
+                }
+            }
+        }
+    }
+
+    /// Writes to the entry with the given id, appending a new entry if it does not exist yet.
+    fn write_at(&mut self, id: u64, text: &str, replace: bool, kind: &LineKind) {
+        if id >= self.next_id() {
+            self.push_back(ScrollbackLine::new(text.to_string(), kind.clone(), 0));
+            return;
+        }
+
+        let Some(line) = self.get_mut(id) else {
+            return;
+        };
+        let mut new_text = if replace {
+            String::new()
+        } else {
+            mem::take(&mut line.text)
+        };
+        new_text.push_str(text);
+        line.set_text(new_text);
+        self.mark_modified(id);
+    }
+
+    /// Appends an output entry which is never collapsed, returning its id.
+    ///
+    /// Used for lines printed with a handle, which are replaced or removed by id later.
+    pub(crate) fn push_updatable(
+        &mut self,
+        text: impl Into<String>,
+        invocation: Option<InvocationId>,
+        channel: Option<String>,
+    ) -> u64 {
+        let id = self.next_id();
+        self.push_back(ScrollbackLine::new(
+            text.into(),
+            LineKind::Output {
+                invocation,
+                channel,
+            },
+            0,
+        ));
+        id
+    }
+
+    /// Replaces the text of an entry, returning `false` if it was evicted, cleared or removed.
+    pub(crate) fn replace(&mut self, id: u64, text: impl Into<String>) -> bool {
+        match self.get_mut(id) {
+            Some(line) if !line.removed => {
+                line.set_text(text.into());
+                self.mark_modified(id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Hides an entry from the console and from dumps.
+    pub(crate) fn remove(&mut self, id: u64) {
+        if let Some(line) = self.get_mut(id) {
+            line.removed = true;
+            line.text.clear();
+            line.layout = None;
+            self.mark_modified(id);
+        }
+    }
+
+    fn push(&mut self, line: ScrollbackLine) {
+        if self.collapse_repeated {
+            if let Some(last) = self.lines.back_mut() {
+                if line.is_repeat_of(last) {
+                    // Keep the newest text so log lines show the latest timestamp
+                    last.repeat += 1;
+                    last.text = line.text;
+                    last.body_start = line.body_start;
+                    last.layout = None;
+                    last.stamp = self.stamp;
+                    self.mark_modified(self.next_id() - 1);
+                    return;
+                }
+            }
+        }
+        self.push_back(line);
+    }
+
+    fn push_back(&mut self, mut line: ScrollbackLine) {
+        line.stamp = self.stamp;
+        if !self
+            .channels
+            .iter()
+            .any(|channel| channel == line.channel())
+        {
+            self.channels.push(line.channel().to_string());
+        }
+        if let (
+            LineKind::Output {
+                invocation: Some(invocation),
+                ..
+            },
+            None,
+        ) = (&line.kind, &line.header)
+        {
+            if let Some(header) = self.block_header(*invocation) {
+                header.replies += 1;
+            }
+        }
+        self.lines.push_back(line);
+        self.evict();
+    }
+
+    fn mark_modified(&mut self, id: u64) {
+        self.modified_from = Some(self.modified_from.map_or(id, |from| from.min(id)));
+    }
+
+    /// Returns the lowest id of the entries changed in place since the last call.
+    pub(crate) fn take_modified(&mut self) -> Option<u64> {
+        self.modified_from.take()
+    }
+
+    /// Drops the oldest entries exceeding the capacity, spilling them to disk if configured.
+    fn evict(&mut self) {
+        while self.lines.len() > self.capacity {
+            let Some(line) = self.lines.pop_front() else {
+                break;
+            };
+            self.first_id += 1;
+            if let Some(spill) = self.spill.as_mut().filter(|_| !line.removed) {
+                spill.write(&line);
+            }
+        }
+        while self
+            .blocks
+            .first_key_value()
+            .is_some_and(|(_, id)| *id < self.first_id)
+        {
+            self.blocks.pop_first();
+        }
+    }
+
+    /// Whether any log record was captured so far.
+    pub(crate) fn has_logs(&self) -> bool {
+        self.has_logs
+    }
+
+    /// Channels lines were printed to so far, in the order they were first used.
+    pub(crate) fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    /// Writes all entries without ANSI styling, returning the number of entries written.
+    pub(crate) fn dump(&self, mut writer: impl Write) -> io::Result<usize> {
+        let mut count = 0;
+        for line in self.iter() {
+            writeln!(writer, "{}", line.unstyled_text())?;
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Iterates over the entries which were not removed, oldest first.
+    pub(crate) fn iter(&self) -> impl DoubleEndedIterator<Item = &ScrollbackLine> {
+        self.lines.iter().filter(|line| !line.removed)
+    }
+
+    /// Returns the entry with the given id, unless it was evicted or cleared.
+    pub(crate) fn get(&self, id: u64) -> Option<&ScrollbackLine> {
+        let index = id.checked_sub(self.first_id)?;
+        self.lines.get(usize::try_from(index).ok()?)
+    }
+
+    /// Returns the entry with the given id, unless it was evicted or cleared.
+    pub(crate) fn get_mut(&mut self, id: u64) -> Option<&mut ScrollbackLine> {
+        let index = id.checked_sub(self.first_id)?;
+        self.lines.get_mut(usize::try_from(index).ok()?)
+    }
+
+    /// Id of the oldest entry still kept.
+    pub(crate) fn first_id(&self) -> u64 {
+        self.first_id
+    }
+
+    /// Id the next appended entry will get.
+    pub(crate) fn next_id(&self) -> u64 {
+        self.first_id + self.lines.len() as u64
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.first_id = self.next_id();
+        self.lines.clear();
+        self.blocks.clear();
+    }
+
+    /// Drops all cached layouts, e.g. after the style or the scale factor changed.
+    pub(crate) fn invalidate_layouts(&mut self) {
+        for line in &mut self.lines {
+            line.layout = None;
+        }
+    }
+}
+
+/// Rows of the scrollback entries passing a [`ScrollbackFilter`].
+///
+/// Only appended and evicted entries are processed on [`update`](Self::update), so the view
+/// stays cheap to maintain for large scrollbacks. It is rebuilt when the filter changes.
+#[derive(Default)]
+pub(crate) struct ScrollbackView {
+    filter: Option<ScrollbackFilter>,
+    /// Bumped every time the view is rebuilt
+    generation: u64,
+    /// Id of the first entry not processed yet
+    next_id: u64,
+    /// Ids of the visible entries and the row each one starts at
+    entries: VecDeque<(u64, usize)>,
+    /// Row following the last visible entry
+    end_row: usize,
+}
+
+impl ScrollbackView {
+    /// Processes the entries appended since the last update, and the ones modified from the id
+    /// returned by [`Scrollback::take_modified`].
+    pub(crate) fn update(
+        &mut self,
+        scrollback: &Scrollback,
+        filter: &ScrollbackFilter,
+        modified_from: Option<u64>,
+    ) {
+        if self.filter.as_ref() != Some(filter) {
+            *self = Self {
+                filter: Some(filter.clone()),
+                generation: self.generation + 1,
+                ..Default::default()
+            };
+        }
+
+        while self
+            .entries
+            .front()
+            .is_some_and(|(id, _)| *id < scrollback.first_id)
+        {
+            self.entries.pop_front();
+        }
+
+        if let Some(modified_from) = modified_from {
+            while self
+                .entries
+                .back()
+                .is_some_and(|(id, _)| *id >= modified_from)
+            {
+                if let Some((_, row)) = self.entries.pop_back() {
+                    self.end_row = row;
+                }
+            }
+            self.next_id = self.next_id.min(modified_from);
+        }
+
+        let next_id = self.next_id.max(scrollback.first_id);
+        let skip = (next_id - scrollback.first_id) as usize;
+        for (id, line) in (next_id..).zip(scrollback.lines.iter().skip(skip)) {
+            if filter.matches(line) && !scrollback.is_folded(line) {
+                self.entries.push_back((id, self.end_row));
+                self.end_row += line.rows;
+            }
+        }
+        self.next_id = scrollback.next_id();
+    }
+
+    /// Changes whenever previously visible entries may have been hidden or reordered.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Ids of the visible entries, starting at the given id.
+    pub(crate) fn ids_from(&self, id: u64) -> impl Iterator<Item = u64> + '_ {
+        let start = self.entries.partition_point(|(entry, _)| *entry < id);
+        self.entries.range(start..).map(|(id, _)| *id)
+    }
+
+    /// Row at which the visible entry with the given id starts.
+    pub(crate) fn row_of(&self, id: u64) -> Option<usize> {
+        let index = self
+            .entries
+            .binary_search_by_key(&id, |(entry, _)| *entry)
+            .ok()?;
+        Some(self.entries[index].1 - self.first_row())
+    }
+
+    fn first_row(&self) -> usize {
+        self.entries.front().map_or(self.end_row, |(_, row)| *row)
+    }
+
+    /// Total number of rows of the visible entries.
+    pub(crate) fn total_rows(&self) -> usize {
+        self.end_row - self.first_row()
+    }
+
+    /// Visible entries overlapping the given rows, with the row each one starts at.
+    pub(crate) fn entries_in(&self, rows: Range<usize>) -> impl Iterator<Item = (u64, usize)> + '_ {
+        let first_row = self.first_row();
+        let start = self
+            .entries
+            .partition_point(|(_, row)| row - first_row <= rows.start)
+            .saturating_sub(1);
+        self.entries
+            .range(start..)
+            .map(move |(id, row)| (*id, row - first_row))
+            .take_while(move |(_, row)| *row < rows.end)
+    }
+}
+
+/// Session log file receiving lines evicted from the scrollback
+struct SpillFile {
+    path: PathBuf,
+    state: SpillState,
+}
+
+enum SpillState {
+    /// Opened on the first eviction
+    Pending,
+    Open(LineWriter<File>),
+    /// Opening or writing failed, which was warned about once, nothing is written anymore
+    Failed,
+}
+
+impl SpillFile {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            state: SpillState::Pending,
+        }
+    }
+
+    fn write(&mut self, line: &ScrollbackLine) {
+        if let SpillState::Pending = self.state {
+            self.state = match OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+            {
+                Ok(file) => SpillState::Open(LineWriter::new(file)),
+                Err(err) => {
+                    warn!(
+                        "Failed to open scrollback spill file {}: {err}",
+                        self.path.display()
+                    );
+                    SpillState::Failed
+                }
+            };
+        }
+
+        let SpillState::Open(file) = &mut self.state else {
+            return;
+        };
+        if let Err(err) = writeln!(file, "{}", line.unstyled_text()) {
+            warn!(
+                "Failed to write scrollback spill file {}: {err}",
+                self.path.display()
+            );
+            self.state = SpillState::Failed;
+        }
+    }
+}
+
+/// Which scrollback lines are shown
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ScrollbackFilter {
+    /// Visibility of each level, indexed like [`LEVELS`]
+    pub(crate) levels: [bool; 5],
+    /// Only show logs whose target starts with this prefix
+    pub(crate) target: Option<String>,
+    /// Only show lines of this channel
+    pub(crate) channel: Option<String>,
+    pub(crate) show_output: bool,
+    pub(crate) show_logs: bool,
+}
+
+impl Default for ScrollbackFilter {
+    fn default() -> Self {
+        Self {
+            levels: [true; 5],
+            target: None,
+            channel: None,
+            show_output: true,
+            show_logs: true,
+        }
+    }
+}
+
+impl ScrollbackFilter {
+    pub(crate) fn matches(&self, line: &ScrollbackLine) -> bool {
+        if line.removed
+            || self
+                .channel
+                .as_ref()
+                .is_some_and(|channel| channel != line.channel())
+        {
+            return false;
+        }
+        match &line.kind {
+            LineKind::Output { .. } => self.show_output,
+            LineKind::Log { level, target } => {
+                self.show_logs
+                    && self.level_visible(*level)
+                    && self
+                        .target
+                        .as_ref()
+                        .is_none_or(|prefix| target.starts_with(prefix.as_str()))
+            }
+        }
+    }
+
+    pub(crate) fn level_visible(&self, level: Level) -> bool {
+        self.levels[level_index(level)]
+    }
+
+    pub(crate) fn set_level_visible(&mut self, level: Level, visible: bool) {
+        self.levels[level_index(level)] = visible;
+    }
+}
+
+fn level_index(level: Level) -> usize {
+    LEVELS.iter().position(|l| *l == level).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    fn record(level: Level, target: &str) -> LogRecord {
+        LogRecord {
+            level,
+            target: target.to_string(),
+            module_path: None,
+            file: None,
+            line: None,
+            spans: Vec::new(),
+            message: "message".to_string(),
+            fields: Vec::new(),
+            timestamp: UNIX_EPOCH,
+        }
+    }
+
+    fn record_at(message: &str, secs: u64) -> LogRecord {
+        LogRecord {
+            message: message.to_string(),
+            timestamp: UNIX_EPOCH + std::time::Duration::from_secs(secs),
+            ..record(Level::WARN, "mygame")
+        }
+    }
+
+    fn collapsing_scrollback() -> Scrollback {
+        Scrollback {
+            collapse_repeated: true,
+            ..Default::default()
+        }
+    }
+
+    fn repeats(scrollback: &Scrollback) -> Vec<usize> {
+        scrollback.iter().map(|line| line.repeat).collect()
+    }
+
+    fn visible_lines(scrollback: &Scrollback, filter: &ScrollbackFilter) -> usize {
+        scrollback
+            .iter()
+            .filter(|line| filter.matches(line))
+            .count()
+    }
+
+    #[test]
+    fn test_default_filter_shows_everything() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_output("$ help");
+        scrollback.push_log(&record(Level::TRACE, "mygame"));
+
+        assert_eq!(visible_lines(&scrollback, &ScrollbackFilter::default()), 2);
+    }
+
+    #[test]
+    fn test_filter_by_level() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_log(&record(Level::ERROR, "mygame"));
+        scrollback.push_log(&record(Level::INFO, "mygame"));
+        scrollback.push_log(&record(Level::INFO, "mygame"));
+
+        let mut filter = ScrollbackFilter::default();
+        filter.set_level_visible(Level::INFO, false);
+        assert_eq!(visible_lines(&scrollback, &filter), 1);
+
+        filter.set_level_visible(Level::INFO, true);
+        assert_eq!(visible_lines(&scrollback, &filter), 3);
+    }
+
+    #[test]
+    fn test_filter_by_target_keeps_output() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_output("$ spawn");
+        scrollback.push_log(&record(Level::INFO, "mygame::ai"));
+        scrollback.push_log(&record(Level::INFO, "wgpu_core"));
+
+        let filter = ScrollbackFilter {
+            target: Some("mygame".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(visible_lines(&scrollback, &filter), 2);
+    }
+
+    #[test]
+    fn test_filter_output_and_logs() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_output("$ spawn");
+        scrollback.push_log(&record(Level::INFO, "mygame"));
+
+        let filter = ScrollbackFilter {
+            show_output: false,
+            ..Default::default()
+        };
+        assert_eq!(visible_lines(&scrollback, &filter), 1);
+
+        let filter = ScrollbackFilter {
+            show_logs: false,
+            ..Default::default()
+        };
+        assert_eq!(visible_lines(&scrollback, &filter), 1);
+    }
+
+    #[test]
+    fn test_filter_by_channel() {
+        let mut scrollback = collapsing_scrollback();
+        scrollback.push_output("$ connect");
+        scrollback.push_log(&record(Level::INFO, "mygame"));
+        scrollback.push_to_channel("joined", None, Some("network".to_string()));
+        scrollback.push_to_channel("joined", None, None);
+        scrollback.push_updatable("ping 12ms", None, Some("network".to_string()));
+
+        assert_eq!(scrollback.channels(), ["commands", "logs", "network"]);
+        assert_eq!(repeats(&scrollback), vec![1, 1, 1, 1, 1]);
+
+        let channel = |channel: &str| ScrollbackFilter {
+            channel: Some(channel.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(visible_lines(&scrollback, &channel("commands")), 2);
+        assert_eq!(visible_lines(&scrollback, &channel("logs")), 1);
+        assert_eq!(visible_lines(&scrollback, &channel("network")), 2);
+
+        scrollback.clear();
+        assert_eq!(scrollback.channels().len(), 3);
+    }
+
+    #[test]
+    fn test_consecutive_output_is_collapsed() {
+        let mut scrollback = collapsing_scrollback();
+        scrollback.push_output("warning");
+        scrollback.push_output("warning");
+        scrollback.push_output("warning");
+        scrollback.push_output("other");
+        scrollback.push_output("warning");
+
+        assert_eq!(repeats(&scrollback), vec![3, 1, 1]);
+        assert_eq!(
+            scrollback.iter().next().unwrap().display_text(),
+            "warning \u{1b}[2m(x3)\u{1b}[0m"
+        );
+    }
+
+    #[test]
+    fn test_logs_differing_only_by_timestamp_are_collapsed() {
+        let mut scrollback = collapsing_scrollback();
+        scrollback.push_log(&record_at("no camera", 1));
+        scrollback.push_log(&record_at("no camera", 2));
+        scrollback.push_log(&record_at("no window", 3));
+
+        assert_eq!(repeats(&scrollback), vec![2, 1]);
+        let newest = record_at("no camera", 2).to_ansi_string();
+        assert_eq!(scrollback.iter().next().unwrap().text, newest);
+    }
+
+    #[test]
+    fn test_output_and_logs_are_not_merged() {
+        let mut scrollback = collapsing_scrollback();
+        let record = record_at("same", 1);
+        scrollback.push_output(record.ansi_body());
+        scrollback.push_log(&record);
+
+        assert_eq!(repeats(&scrollback), vec![1, 1]);
+    }
+
+    #[test]
+    fn test_no_collapsing_when_disabled() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_output("warning");
+        scrollback.push_output("warning");
+
+        assert_eq!(repeats(&scrollback), vec![1, 1]);
+    }
+
+    #[test]
+    fn test_empty_lines_are_not_collapsed() {
+        let mut scrollback = collapsing_scrollback();
+        scrollback.push_output("");
+        scrollback.push_output("");
+
+        assert_eq!(repeats(&scrollback), vec![1, 1]);
+    }
+
+    #[test]
+    fn test_oldest_lines_are_evicted() {
+        let mut scrollback = Scrollback {
+            capacity: 2,
+            ..Default::default()
+        };
+        scrollback.push_output("1");
+        scrollback.push_output("2");
+        scrollback.push_output("3");
+
+        let lines = scrollback
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec!["2", "3"]);
+    }
+
+    #[test]
+    fn test_zero_size_keeps_the_last_line() {
+        let mut scrollback = Scrollback::default();
+        scrollback.configure(&ConsoleConfiguration {
+            scrollback_size: 0,
+            ..Default::default()
+        });
+        scrollback.push_output("1");
+        scrollback.push_output("2");
+        assert_eq!(texts(&scrollback), vec!["2"]);
+    }
+
+    #[test]
+    fn test_evicted_lines_are_spilled_to_file() {
+        let path = std::env::temp_dir().join(format!(
+            "bevy_console_spill_test_{}.log",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let mut scrollback = Scrollback {
+            capacity: 1,
+            collapse_repeated: true,
+            spill: Some(SpillFile::new(path.clone())),
+            ..Default::default()
+        };
+        scrollback.push_output("\u{1b}[31mred\u{1b}[0m");
+        scrollback.push_output("twice");
+        scrollback.push_output("twice");
+        scrollback.push_output("kept");
+        drop(scrollback);
+
+        let spilled = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(spilled, "red\ntwice (x2)\n");
+    }
+
+    #[test]
+    fn test_spill_file_is_not_reopened_after_failure() {
+        let path = std::env::temp_dir()
+            .join(format!("bevy_console_missing_{}", std::process::id()))
+            .join("spill.log");
+
+        let mut scrollback = Scrollback {
+            capacity: 1,
+            spill: Some(SpillFile::new(path.clone())),
+            ..Default::default()
+        };
+        scrollback.push_output("a");
+        scrollback.push_output("b");
+        assert!(matches!(
+            scrollback.spill.as_ref().unwrap().state,
+            SpillState::Failed
+        ));
+
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        scrollback.push_output("c");
+        let created = path.exists();
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+        assert!(!created);
+    }
+
+    #[test]
+    fn test_dump_strips_styling() {
+        let mut scrollback = collapsing_scrollback();
+        scrollback.push_output("$ help");
+        scrollback.push_output("\u{1b}[1mbold\u{1b}[0m");
+        scrollback.push_output("\u{1b}[1mbold\u{1b}[0m");
+
+        let mut dump = Vec::new();
+        assert_eq!(scrollback.dump(&mut dump).unwrap(), 2);
+        assert_eq!(String::from_utf8(dump).unwrap(), "$ help\nbold (x2)\n");
+    }
+
+    fn view_entries(view: &ScrollbackView, rows: Range<usize>) -> Vec<(u64, usize)> {
+        view.entries_in(rows).collect()
+    }
+
+    #[test]
+    fn test_view_rows_of_multiline_entries() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_output("a");
+        scrollback.push_output("b\nc\nd");
+        scrollback.push_output("e");
+
+        let mut view = ScrollbackView::default();
+        view.update(&scrollback, &ScrollbackFilter::default(), None);
+
+        assert_eq!(view.total_rows(), 5);
+        assert_eq!(view_entries(&view, 0..5), vec![(0, 0), (1, 1), (2, 4)]);
+        assert_eq!(view_entries(&view, 2..4), vec![(1, 1)]);
+        assert_eq!(view_entries(&view, 4..5), vec![(2, 4)]);
+    }
+
+    #[test]
+    fn test_view_follows_appends_and_evictions() {
+        let mut scrollback = Scrollback {
+            capacity: 3,
+            ..Default::default()
+        };
+        let mut view = ScrollbackView::default();
+        let filter = ScrollbackFilter::default();
+
+        scrollback.push_output("1");
+        scrollback.push_output("2\n2");
+        view.update(&scrollback, &filter, None);
+        assert_eq!(view.total_rows(), 3);
+
+        scrollback.push_output("3");
+        scrollback.push_output("4");
+        view.update(&scrollback, &filter, None);
+        assert_eq!(view.total_rows(), 4);
+        assert_eq!(view_entries(&view, 0..4), vec![(1, 0), (2, 2), (3, 3)]);
+
+        scrollback.clear();
+        scrollback.push_output("5");
+        view.update(&scrollback, &filter, None);
+        assert_eq!(view_entries(&view, 0..1), vec![(4, 0)]);
+    }
+
+    #[test]
+    fn test_view_is_rebuilt_when_filter_changes() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_output("$ spawn");
+        scrollback.push_log(&record(Level::INFO, "mygame"));
+
+        let mut view = ScrollbackView::default();
+        view.update(&scrollback, &ScrollbackFilter::default(), None);
+        assert_eq!(view.total_rows(), 2);
+
+        let filter = ScrollbackFilter {
+            show_output: false,
+            ..Default::default()
+        };
+        view.update(&scrollback, &filter, None);
+        assert_eq!(view_entries(&view, 0..1), vec![(1, 0)]);
+    }
+
+    fn texts(scrollback: &Scrollback) -> Vec<String> {
+        scrollback.iter().map(|line| line.text.clone()).collect()
+    }
+
+    #[test]
+    fn test_carriage_return_replaces_last_line() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_output("$ load");
+        scrollback.push_output("loading 10%");
+        scrollback.push_output("\rloading 50%");
+        scrollback.push_output("abc\rxy");
+
+        assert_eq!(texts(&scrollback), vec!["$ load", "loading 50%", "xy"]);
+        assert_eq!(scrollback.take_modified(), Some(1));
+        assert_eq!(scrollback.take_modified(), None);
+    }
+
+    #[test]
+    fn test_crlf_is_an_ordinary_newline() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_output("a\r\nb\r\n");
+        assert_eq!(texts(&scrollback), vec!["a\nb\n"]);
+
+        scrollback.push_output("\u{1b}[1Ac\r\nd");
+        assert_eq!(texts(&scrollback), vec!["c", "d"]);
+    }
+
+    #[test]
+    fn test_rewrite_keeps_kind_and_body_start() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_log(&record_at("progress 1/2", 0));
+        let body_start = scrollback.iter().next().unwrap().body_start;
+        scrollback.push_log(&record_at("\rprogress 2/2", 1));
+
+        let line = scrollback.iter().next().unwrap();
+        assert!(matches!(line.kind, LineKind::Log { .. }));
+        assert_eq!(line.body_start, body_start);
+        assert!(line.body().contains("progress 2/2"));
+        assert!(!line.body().contains("00:00:01"));
+    }
+
+    #[test]
+    fn test_cursor_up_and_erase_line() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_output("a: 0%");
+        scrollback.push_output("b: 0%");
+        scrollback.push_output("\u{1b}[1A\u{1b}[2Kb: 50%");
+        assert_eq!(texts(&scrollback), vec!["a: 0%", "b: 50%"]);
+
+        scrollback.push_output("x");
+        scrollback.push_output("\u{1b}[2A\u{1b}[2Kb: 100%\n\u{1b}[2K\u{1b}[32mdone");
+        assert_eq!(
+            texts(&scrollback),
+            vec!["a: 0%", "b: 100%", "\u{1b}[32mdone"]
+        );
+
+        // Each entry counts as one line when moving the cursor
+        scrollback.push_output("1\n2");
+        scrollback.push_output("\u{1b}[1A\u{1b}[2K3");
+        assert_eq!(texts(&scrollback)[3], "3");
+    }
+
+    #[test]
+    fn test_rewrite_keeps_graphics_parameters() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_output("0%");
+        scrollback.push_output("\r\u{1b}[1;38;5;208m50%\u{1b}[0m");
+        assert_eq!(texts(&scrollback), vec!["\u{1b}[1;38;5;208m50%\u{1b}[0m"]);
+    }
+
+    #[test]
+    fn test_cursor_movement_is_clamped_to_scrollback() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_output("a");
+        scrollback.push_output("\u{1b}[5Ab");
+        scrollback.push_output("\u{1b}[5Bc");
+        assert_eq!(texts(&scrollback), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn test_log_carriage_return_keeps_prefix() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_log(&record_at("progress 1/2", 0));
+        scrollback.push_log(&record_at("\rprogress 2/2", 1));
+
+        assert_eq!(scrollback.iter().count(), 1);
+        let text = scrollback.iter().next().unwrap().unstyled_text();
+        assert!(text.contains("00:00:01"), "{text}");
+        assert!(text.ends_with("WARN progress 2/2"), "{text}");
+        assert!(scrollback.has_logs());
+    }
+
+    #[test]
+    fn test_view_follows_in_place_updates() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_output("$ load");
+        scrollback.push_output("1%");
+        let mut view = ScrollbackView::default();
+        let filter = ScrollbackFilter::default();
+        let modified_from = scrollback.take_modified();
+        view.update(&scrollback, &filter, modified_from);
+
+        scrollback.push_output("\r1%\n2%\n3%");
+        let modified_from = scrollback.take_modified();
+        view.update(&scrollback, &filter, modified_from);
+        assert_eq!(view.total_rows(), 4);
+        assert_eq!(
+            view_entries(&view, 0..4),
+            vec![(0, 0), (1, 1), (2, 2), (3, 3)]
+        );
+
+        scrollback.push_output("\u{1b}[2F\u{1b}[K50%\n\u{1b}[2Kdone\nx");
+        let modified_from = scrollback.take_modified();
+        view.update(&scrollback, &filter, modified_from);
+        assert_eq!(texts(&scrollback), vec!["$ load", "1%", "50%", "done", "x"]);
+        assert_eq!(view.total_rows(), 5);
+        assert_eq!(view_entries(&view, 3..5), vec![(3, 3), (4, 4)]);
+    }
+
+    #[test]
+    fn test_updatable_lines_are_replaced_and_removed() {
+        let mut scrollback = collapsing_scrollback();
+        scrollback.push_output("$ load");
+        let id = scrollback.push_updatable("$ load", None, None);
+        scrollback.push_output("after");
+        assert_eq!(repeats(&scrollback), vec![1, 1, 1]);
+
+        assert!(scrollback.replace(id, "loaded\nall"));
+        assert_eq!(texts(&scrollback), vec!["$ load", "loaded\nall", "after"]);
+        assert_eq!(scrollback.take_modified(), Some(id));
+
+        let mut view = ScrollbackView::default();
+        view.update(&scrollback, &ScrollbackFilter::default(), None);
+        assert_eq!(view.total_rows(), 4);
+
+        scrollback.remove(id);
+        let modified_from = scrollback.take_modified();
+        view.update(&scrollback, &ScrollbackFilter::default(), modified_from);
+        assert_eq!(view_entries(&view, 0..2), vec![(0, 0), (2, 1)]);
+        assert_eq!(texts(&scrollback), vec!["$ load", "after"]);
+        assert!(!scrollback.replace(id, "again"));
+    }
+
+    #[test]
+    fn test_entries_are_stamped() {
+        let stamp = LineStamp {
+            wall: UNIX_EPOCH + std::time::Duration::from_millis(3_723_450),
+            elapsed: std::time::Duration::from_millis(12_500),
+            frame: 42,
+        };
+        assert_eq!(stamp.format(), "01:02:03.450    12.500s #42     ");
+
+        let mut scrollback = collapsing_scrollback();
+        scrollback.push_output("before");
+        scrollback.set_stamp(Some(stamp));
+        scrollback.push_output("a");
+        let later = LineStamp { frame: 43, ..stamp };
+        scrollback.set_stamp(Some(later));
+        scrollback.push_output("a");
+        let id = scrollback.push_updatable("b", None, None);
+        scrollback.replace(id, "c");
+
+        let stamps = scrollback
+            .iter()
+            .map(|line| line.stamp.map(|stamp| stamp.frame))
+            .collect::<Vec<_>>();
+        assert_eq!(stamps, vec![None, Some(43), Some(43)]);
+    }
+
+    fn header(scrollback: &Scrollback, id: u64) -> BlockHeader {
+        scrollback.get(id).unwrap().header.clone().unwrap()
+    }
+
+    #[test]
+    fn test_folded_block_hides_replies() {
+        let mut scrollback = Scrollback::default();
+        let load = InvocationId::next();
+        scrollback.push_command("$ load", load);
+        scrollback.push_reply("loading", Some(load));
+        scrollback.push_log(&record(Level::INFO, "mygame"));
+        scrollback.push_reply("done", Some(load));
+        scrollback.push_reply("[ok]", Some(load));
+        scrollback.push_output("unrelated");
+
+        assert_eq!(
+            header(&scrollback, 0),
+            BlockHeader {
+                status: Some(BlockStatus::Ok),
+                folded: false,
+                replies: 3,
+            }
+        );
+
+        let filter = ScrollbackFilter::default();
+        let mut view = ScrollbackView::default();
+        view.update(&scrollback, &filter, None);
+        scrollback.toggle_fold(load);
+        let modified_from = scrollback.take_modified();
+        view.update(&scrollback, &filter, modified_from);
+        // Logs printed while the command ran are not part of its block
+        assert_eq!(view.ids_from(0).collect::<Vec<_>>(), vec![0, 2, 5]);
+
+        scrollback.toggle_fold(load);
+        let modified_from = scrollback.take_modified();
+        view.update(&scrollback, &filter, modified_from);
+        assert_eq!(view.total_rows(), 6);
+    }
+
+    #[test]
+    fn test_replies_of_other_commands_are_not_collapsed() {
+        let mut scrollback = collapsing_scrollback();
+        let (a, b) = (InvocationId::next(), InvocationId::next());
+        scrollback.push_command("$ ping", a);
+        scrollback.push_reply("pong", Some(a));
+        scrollback.push_command("$ ping", b);
+        scrollback.push_reply("pong", Some(b));
+        scrollback.push_reply("pong", Some(b));
+        scrollback.push_reply("[cancelled]", Some(b));
+
+        assert_eq!(repeats(&scrollback), vec![1, 1, 1, 2, 1]);
+        assert_eq!(header(&scrollback, 0).status, None);
+        assert_eq!(header(&scrollback, 2).status, Some(BlockStatus::Cancelled));
+    }
+
+    #[test]
+    fn test_eviction_forgets_blocks() {
+        let mut scrollback = Scrollback {
+            capacity: 2,
+            ..Default::default()
+        };
+        let old = InvocationId::next();
+        scrollback.push_command("$ old", old);
+        scrollback.push_reply("a", Some(old));
+        scrollback.push_reply("b", Some(old));
+        scrollback.toggle_fold(old);
+
+        assert!(scrollback.blocks.is_empty());
+        assert!(scrollback.iter().all(|line| line.header.is_none()));
+    }
+}
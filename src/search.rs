@@ -0,0 +1,311 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use bevy_egui::egui::text::LayoutJob;
+use bevy_egui::egui::Color32;
+use regex::{Regex, RegexBuilder};
+
+use crate::color::{plain_text, restyle_ranges};
+use crate::scrollback::{Scrollback, ScrollbackView};
+
+const MATCH_COLOR: Color32 = Color32::from_rgb(100, 80, 0);
+const CURRENT_MATCH_COLOR: Color32 = Color32::from_rgb(190, 110, 0);
+
+/// A match of the search pattern, as a byte range into the plain text of an entry
+#[derive(Debug, PartialEq)]
+struct SearchMatch {
+    id: u64,
+    range: Range<usize>,
+}
+
+/// Search bar of the scrollback, opened with Ctrl+F.
+///
+/// Matches are searched in the visible entries with ANSI escape sequences removed.
+#[derive(Default)]
+pub(crate) struct ScrollbackSearch {
+    pub(crate) open: bool,
+    pub(crate) query: String,
+    pub(crate) match_case: bool,
+    pub(crate) regex: bool,
+    /// Error of an invalid regex query
+    pub(crate) error: Option<String>,
+    /// Query, case and regex mode the matches were searched with
+    searched: Option<(String, bool, bool)>,
+    pattern: Option<Regex>,
+    matches: VecDeque<SearchMatch>,
+    /// Index of the match jumped to last
+    current: Option<usize>,
+    view_generation: u64,
+    /// Id of the first entry not searched yet
+    next_id: u64,
+}
+
+impl ScrollbackSearch {
+    /// Brings the matches up to date with the query and the visible entries.
+    ///
+    /// Entries from `modified_from` on are searched again, as they were changed in place.
+    ///
+    /// Returns `true` if all matches were searched again, making highlighted layouts stale.
+    pub(crate) fn update(
+        &mut self,
+        scrollback: &Scrollback,
+        view: &ScrollbackView,
+        modified_from: Option<u64>,
+    ) -> bool {
+        let settings = self
+            .open
+            .then(|| (self.query.clone(), self.match_case, self.regex));
+        let rebuild = settings != self.searched || view.generation() != self.view_generation;
+        if rebuild {
+            self.pattern = None;
+            self.error = None;
+            self.matches.clear();
+            self.current = None;
+            self.next_id = 0;
+            self.view_generation = view.generation();
+            if let Some((query, match_case, regex)) = &settings {
+                if !query.is_empty() {
+                    match build_pattern(query, *match_case, *regex) {
+                        Ok(pattern) => self.pattern = Some(pattern),
+                        Err(err) => self.error = Some(err.to_string()),
+                    }
+                }
+            }
+            self.searched = settings;
+        }
+
+        let Some(pattern) = &self.pattern else {
+            return rebuild;
+        };
+
+        let mut evicted = 0;
+        while self
+            .matches
+            .front()
+            .is_some_and(|m| m.id < scrollback.first_id())
+        {
+            self.matches.pop_front();
+            evicted += 1;
+        }
+        self.current = self
+            .current
+            .and_then(|current| current.checked_sub(evicted));
+
+        let start = modified_from.map_or(self.next_id, |from| from.min(self.next_id));
+        while self.matches.back().is_some_and(|m| m.id >= start) {
+            self.matches.pop_back();
+        }
+        self.current = self.current.filter(|current| *current < self.matches.len());
+
+        for id in view.ids_from(start) {
+            let Some(line) = scrollback.get(id) else {
+                continue;
+            };
+            let text = plain_text(&line.display_text());
+            self.matches
+                .extend(
+                    pattern
+                        .find_iter(&text)
+                        .filter(|m| !m.is_empty())
+                        .map(|m| SearchMatch {
+                            id,
+                            range: m.range(),
+                        }),
+                );
+        }
+        self.next_id = scrollback.next_id();
+
+        rebuild
+    }
+
+    pub(crate) fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// Position of the match jumped to last, starting at one.
+    pub(crate) fn current_position(&self) -> Option<usize> {
+        self.current.map(|current| current + 1)
+    }
+
+    /// Moves to the next match, or the previous one if `backwards`, wrapping around at the ends.
+    ///
+    /// Returns the id of the entry containing the match.
+    pub(crate) fn jump(&mut self, backwards: bool) -> Option<u64> {
+        let count = self.matches.len();
+        if count == 0 {
+            return None;
+        }
+        let current = match (self.current, backwards) {
+            (None, false) => 0,
+            (None, true) => count - 1,
+            (Some(current), false) => (current + 1) % count,
+            (Some(current), true) => (current + count - 1) % count,
+        };
+        self.current = Some(current);
+        Some(self.matches[current].id)
+    }
+
+    /// Highlights the matches of an entry in its layout, whose text must be the plain text.
+    pub(crate) fn highlight(&self, id: u64, job: &mut LayoutJob) {
+        let start = self.matches.partition_point(|m| m.id < id);
+        let highlights = self
+            .matches
+            .range(start..)
+            .take_while(|m| m.id == id)
+            .enumerate()
+            .map(|(i, m)| {
+                let color = if self.current == Some(start + i) {
+                    CURRENT_MATCH_COLOR
+                } else {
+                    MATCH_COLOR
+                };
+                (m.range.clone(), color)
+            })
+            .collect::<Vec<_>>();
+        if !highlights.is_empty() {
+            set_backgrounds(job, &highlights);
+        }
+    }
+}
+
+fn build_pattern(query: &str, match_case: bool, regex: bool) -> Result<Regex, regex::Error> {
+    let pattern = if regex {
+        query.to_string()
+    } else {
+        regex::escape(query)
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!match_case)
+        .build()
+}
+
+/// Sets the background color of the given sorted byte ranges of `job`.
+fn set_backgrounds(job: &mut LayoutJob, ranges: &[(Range<usize>, Color32)]) {
+    let byte_ranges = ranges
+        .iter()
+        .map(|(range, _)| range.clone())
+        .collect::<Vec<_>>();
+    restyle_ranges(job, &byte_ranges, |i, format| {
+        format.background = ranges[i].1
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_egui::egui::{FontId, TextFormat};
+
+    use super::*;
+
+    fn open_search(query: &str, match_case: bool, regex: bool) -> ScrollbackSearch {
+        ScrollbackSearch {
+            open: true,
+            query: query.to_string(),
+            match_case,
+            regex,
+            ..Default::default()
+        }
+    }
+
+    fn match_ids(search: &ScrollbackSearch) -> Vec<u64> {
+        search.matches.iter().map(|m| m.id).collect()
+    }
+
+    fn scrollback(lines: &[&str]) -> (Scrollback, ScrollbackView) {
+        let mut scrollback = Scrollback::default();
+        for line in lines {
+            scrollback.push_output(*line);
+        }
+        let mut view = ScrollbackView::default();
+        view.update(&scrollback, &Default::default(), None);
+        (scrollback, view)
+    }
+
+    #[test]
+    fn test_search_ignores_case_and_ansi() {
+        let (scrollback, view) = scrollback(&["\u{1b}[31mError\u{1b}[0m: x", "fine", "error"]);
+
+        let mut search = open_search("error", false, false);
+        assert!(search.update(&scrollback, &view, None));
+        assert_eq!(match_ids(&search), vec![0, 2]);
+        assert_eq!(search.matches[0].range, 0..5);
+
+        search.match_case = true;
+        search.update(&scrollback, &view, None);
+        assert_eq!(match_ids(&search), vec![2]);
+    }
+
+    #[test]
+    fn test_search_regex() {
+        let (scrollback, view) = scrollback(&["id=12", "id=x", "a.b"]);
+
+        let mut search = open_search(r"id=\d+", false, true);
+        search.update(&scrollback, &view, None);
+        assert_eq!(match_ids(&search), vec![0]);
+
+        // Without regex mode the query is matched literally
+        let mut search = open_search(".", false, false);
+        search.update(&scrollback, &view, None);
+        assert_eq!(match_ids(&search), vec![2]);
+    }
+
+    #[test]
+    fn test_invalid_regex_reports_error() {
+        let (scrollback, view) = scrollback(&["a"]);
+
+        let mut search = open_search("(", false, true);
+        search.update(&scrollback, &view, None);
+        assert!(search.error.is_some());
+        assert_eq!(search.match_count(), 0);
+    }
+
+    #[test]
+    fn test_new_lines_are_searched() {
+        let (mut scrollback, mut view) = scrollback(&["hit"]);
+        let mut search = open_search("hit", false, false);
+        search.update(&scrollback, &view, None);
+
+        scrollback.push_output("miss");
+        scrollback.push_output("hit hit");
+        view.update(&scrollback, &Default::default(), None);
+        assert!(!search.update(&scrollback, &view, None));
+        assert_eq!(match_ids(&search), vec![0, 2, 2]);
+    }
+
+    #[test]
+    fn test_jump_wraps_around() {
+        let (scrollback, view) = scrollback(&["a", "b", "a"]);
+        let mut search = open_search("a", false, false);
+        search.update(&scrollback, &view, None);
+
+        assert_eq!(search.jump(false), Some(0));
+        assert_eq!(search.jump(false), Some(2));
+        assert_eq!(search.jump(false), Some(0));
+        assert_eq!(search.jump(true), Some(2));
+        assert_eq!(search.current_position(), Some(2));
+    }
+
+    #[test]
+    fn test_set_backgrounds_splits_sections() {
+        let mut job = LayoutJob::default();
+        let format = TextFormat::simple(FontId::monospace(14.0), Color32::WHITE);
+        job.append("abc", 0.0, format.clone());
+        job.append("def", 0.0, format);
+
+        set_backgrounds(&mut job, &[(1..4, Color32::RED)]);
+
+        let sections = job
+            .sections
+            .iter()
+            .map(|section| (section.byte_range.clone(), section.format.background))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sections,
+            vec![
+                (0..1, Color32::TRANSPARENT),
+                (1..3, Color32::RED),
+                (3..4, Color32::RED),
+                (4..6, Color32::TRANSPARENT),
+            ]
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use crate::color::plain_text;
+use crate::scrollback::{Scrollback, ScrollbackView};
+
+/// Position in the scrollback, as an entry id and a character index into its plain text
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(crate) struct TextPoint {
+    pub(crate) id: u64,
+    pub(crate) index: usize,
+}
+
+/// Text selected by dragging over the scrollback, which may span several entries.
+#[derive(Default)]
+pub(crate) struct ScrollbackSelection {
+    /// Where the drag started
+    anchor: Option<TextPoint>,
+    /// Where the pointer is, or was released
+    head: Option<TextPoint>,
+    dragging: bool,
+}
+
+/// Text to copy from the scrollback, always without ANSI styling
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CopyAction {
+    Line(u64),
+    Selection,
+    /// Entries passing the current filter
+    Visible,
+    All,
+}
+
+impl ScrollbackSelection {
+    pub(crate) fn begin(&mut self, point: TextPoint) {
+        self.anchor = Some(point);
+        self.head = Some(point);
+        self.dragging = true;
+    }
+
+    /// Moves the end of the selection while dragging.
+    pub(crate) fn extend(&mut self, point: TextPoint) {
+        if self.dragging {
+            self.head = Some(point);
+        }
+    }
+
+    pub(crate) fn end_drag(&mut self) {
+        self.dragging = false;
+    }
+
+    pub(crate) fn is_dragging(&self) -> bool {
+        self.dragging
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Start and end of the selection in scrollback order, `None` if nothing is selected.
+    fn range(&self) -> Option<(TextPoint, TextPoint)> {
+        let (anchor, head) = (self.anchor?, self.head?);
+        (anchor != head).then(|| (anchor.min(head), anchor.max(head)))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.range().is_none()
+    }
+
+    /// Selected character range of an entry with `char_count` characters.
+    pub(crate) fn chars_of(&self, id: u64, char_count: usize) -> Option<(usize, usize)> {
+        let (start, end) = self.range()?;
+        if id < start.id || id > end.id {
+            return None;
+        }
+        let from = if id == start.id { start.index } else { 0 };
+        let to = if id == end.id { end.index } else { char_count };
+        (from < to).then_some((from, to.min(char_count)))
+    }
+
+    /// Selected text of the entries shown in `view`, one line per entry.
+    pub(crate) fn text(&self, scrollback: &Scrollback, view: &ScrollbackView) -> Option<String> {
+        let (start, end) = self.range()?;
+        let lines = view
+            .ids_from(start.id)
+            .take_while(|id| *id <= end.id)
+            .filter_map(|id| {
+                let text = plain_text(&scrollback.get(id)?.display_text());
+                let (from, to) = self.chars_of(id, text.chars().count())?;
+                Some(text.chars().skip(from).take(to - from).collect::<String>())
+            })
+            .collect::<Vec<_>>();
+        Some(lines.join("\n"))
+    }
+}
+
+impl CopyAction {
+    /// Plain text to copy, `None` if there is nothing.
+    pub(crate) fn text(
+        self,
+        scrollback: &Scrollback,
+        view: &ScrollbackView,
+        selection: &ScrollbackSelection,
+    ) -> Option<String> {
+        let lines = |ids: &mut dyn Iterator<Item = u64>| {
+            ids.filter_map(|id| Some(plain_text(&scrollback.get(id)?.display_text())))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        match self {
+            CopyAction::Line(id) => Some(lines(&mut std::iter::once(id))),
+            CopyAction::Selection => selection.text(scrollback, view),
+            CopyAction::Visible => Some(lines(&mut view.ids_from(0))),
+            CopyAction::All => Some(
+                scrollback
+                    .iter()
+                    .map(|line| plain_text(&line.display_text()))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scrollback::ScrollbackFilter;
+
+    fn point(id: u64, index: usize) -> TextPoint {
+        TextPoint { id, index }
+    }
+
+    fn scrollback(lines: &[&str]) -> (Scrollback, ScrollbackView) {
+        let mut scrollback = Scrollback::default();
+        for line in lines {
+            scrollback.push_output(*line);
+        }
+        let mut view = ScrollbackView::default();
+        view.update(&scrollback, &ScrollbackFilter::default(), None);
+        (scrollback, view)
+    }
+
+    #[test]
+    fn test_selection_within_a_line() {
+        let (scrollback, view) = scrollback(&["\u{1b}[31merror\u{1b}[0m: entity 4v1 despawned"]);
+        let mut selection = ScrollbackSelection::default();
+        selection.begin(point(0, 14));
+        selection.extend(point(0, 17));
+        selection.end_drag();
+        selection.extend(point(0, 20));
+
+        assert_eq!(selection.chars_of(0, 29), Some((14, 17)));
+        assert_eq!(selection.text(&scrollback, &view).unwrap(), "4v1");
+    }
+
+    #[test]
+    fn test_selection_across_lines_backwards() {
+        let (scrollback, view) = scrollback(&["first", "second", "third"]);
+        let mut selection = ScrollbackSelection::default();
+        selection.begin(point(2, 3));
+        selection.extend(point(0, 2));
+
+        assert_eq!(selection.chars_of(1, 6), Some((0, 6)));
+        assert_eq!(
+            selection.text(&scrollback, &view).unwrap(),
+            "rst\nsecond\nthi"
+        );
+    }
+
+    #[test]
+    fn test_empty_selection() {
+        let mut selection = ScrollbackSelection::default();
+        assert!(selection.is_empty());
+        selection.begin(point(0, 1));
+        assert!(selection.is_empty());
+        assert_eq!(selection.chars_of(0, 5), None);
+    }
+
+    #[test]
+    fn test_copy_actions_strip_styling() {
+        let (scrollback, view) = scrollback(&["\u{1b}[1ma\u{1b}[0m", "b"]);
+        let selection = ScrollbackSelection::default();
+
+        let copy = |action: CopyAction| action.text(&scrollback, &view, &selection);
+        assert_eq!(copy(CopyAction::Line(0)).unwrap(), "a");
+        assert_eq!(copy(CopyAction::Visible).unwrap(), "a\nb");
+        assert_eq!(copy(CopyAction::All).unwrap(), "a\nb");
+        assert_eq!(copy(CopyAction::Selection), None);
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::fs;
+
+use bevy::log::Level;
+use bevy::prelude::*;
+use bevy_egui::egui::{pos2, vec2, Pos2, Vec2};
+
+use crate::console::ConsoleState;
+use crate::scrollback::{ScrollbackFilter, LEVELS};
+use crate::{ConsoleConfiguration, ConsoleOpen};
+
+/// Console state kept between runs in [`ConsoleConfiguration::session_path`]
+///
+/// Saved as `key=value` lines, unknown keys and invalid values are ignored when loading so
+/// the file keeps working across versions.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ConsoleSession {
+    /// Top left corner of the console window
+    pub(crate) window_pos: Option<Pos2>,
+    /// Size of the contents of the console window
+    pub(crate) window_size: Option<Vec2>,
+    pub(crate) open: bool,
+    /// Selected channel tab and filter toggles
+    pub(crate) filter: ScrollbackFilter,
+}
+
+impl ConsoleSession {
+    pub(crate) fn new(state: &ConsoleState, open: bool) -> Self {
+        Self {
+            window_pos: state.window_pos,
+            window_size: state.window_size,
+            open,
+            filter: state.filter.clone(),
+        }
+    }
+
+    pub(crate) fn parse(text: &str) -> Self {
+        let mut session = Self::default();
+        let mut pos = [None; 2];
+        let mut size = [None; 2];
+        for (key, value) in text.lines().filter_map(|line| line.split_once('=')) {
+            let value = value.trim();
+            let number = || value.parse::<f32>().ok().filter(|value| value.is_finite());
+            let flag = || value.parse::<bool>().ok();
+            let text = || (!value.is_empty()).then(|| value.to_string());
+            match key.trim() {
+                "left" => pos[0] = number(),
+                "top" => pos[1] = number(),
+                "width" => size[0] = number().filter(|width| *width > 0.0),
+                "height" => size[1] = number().filter(|height| *height > 0.0),
+                "open" => session.open = flag().unwrap_or_default(),
+                "channel" => session.filter.channel = text(),
+                "target" => session.filter.target = text(),
+                "output" => session.filter.show_output = flag().unwrap_or(true),
+                "logs" => session.filter.show_logs = flag().unwrap_or(true),
+                "levels" => {
+                    let visible = value.split(',').map(str::trim).collect::<Vec<_>>();
+                    for level in LEVELS {
+                        let name = level_name(level);
+                        session
+                            .filter
+                            .set_level_visible(level, visible.contains(&name.as_str()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let [Some(left), Some(top)] = pos {
+            session.window_pos = Some(pos2(left, top));
+        }
+        if let [Some(width), Some(height)] = size {
+            session.window_size = Some(vec2(width, height));
+        }
+        session
+    }
+}
+
+impl fmt::Display for ConsoleSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(pos) = self.window_pos {
+            writeln!(f, "left={}\ntop={}", pos.x, pos.y)?;
+        }
+        if let Some(size) = self.window_size {
+            writeln!(f, "width={}\nheight={}", size.x, size.y)?;
+        }
+        let levels = LEVELS
+            .iter()
+            .filter(|level| self.filter.level_visible(**level))
+            .map(|level| level_name(*level))
+            .collect::<Vec<_>>();
+        writeln!(f, "open={}", self.open)?;
+        writeln!(
+            f,
+            "channel={}",
+            self.filter.channel.as_deref().unwrap_or("")
+        )?;
+        writeln!(f, "target={}", self.filter.target.as_deref().unwrap_or(""))?;
+        writeln!(f, "output={}", self.filter.show_output)?;
+        writeln!(f, "logs={}", self.filter.show_logs)?;
+        writeln!(f, "levels={}", levels.join(","))
+    }
+}
+
+fn level_name(level: Level) -> String {
+    level.as_str().to_lowercase()
+}
+
+/// Restores the console state saved by a previous run, if configured.
+pub(crate) fn load_session(
+    config: Res<ConsoleConfiguration>,
+    mut state: ResMut<ConsoleState>,
+    mut console_open: ResMut<ConsoleOpen>,
+) {
+    let Some(path) = &config.session_path else {
+        return;
+    };
+    // Nothing was saved yet on the first run
+    let Ok(text) = fs::read_to_string(path) else {
+        return;
+    };
+    let session = ConsoleSession::parse(&text);
+    state.window_pos = session.window_pos;
+    state.window_size = session.window_size;
+    state.filter = session.filter.clone();
+    console_open.open = session.open;
+    state.saved_session = Some(session);
+}
+
+/// Writes the console state once it changed, if configured.
+///
+/// Not called while the pointer is dragging, so moving or resizing the window is saved once.
+pub(crate) fn save_session(config: &ConsoleConfiguration, state: &mut ConsoleState, open: bool) {
+    let Some(path) = &config.session_path else {
+        return;
+    };
+    let session = ConsoleSession::new(state, open);
+    if state.saved_session.as_ref() == Some(&session) {
+        return;
+    }
+    if let Err(err) = fs::write(path, session.to_string()) {
+        warn!(
+            "Failed to save the console state to {}: {err}",
+            path.display()
+        );
+    }
+    // Not retried every frame if writing failed
+    state.saved_session = Some(session);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_round_trip() {
+        let mut session = ConsoleSession {
+            window_pos: Some(pos2(1290.5, -4.0)),
+            window_size: Some(vec2(600.0, 400.0)),
+            open: true,
+            ..Default::default()
+        };
+        session.filter.channel = Some("network".to_string());
+        session.filter.target = Some("mygame::ai".to_string());
+        session.filter.show_output = false;
+        session.filter.set_level_visible(Level::TRACE, false);
+        session.filter.set_level_visible(Level::DEBUG, false);
+
+        let text = session.to_string();
+        assert!(text.contains("levels=error,warn,info\n"));
+        assert_eq!(ConsoleSession::parse(&text), session);
+    }
+
+    #[test]
+    fn test_parse_ignores_invalid_lines() {
+        let session = ConsoleSession::parse(
+            "left=10\ntop=NaN\nwidth=-1\nheight=300\nopen=yes\nfont=big\nnot a key\n",
+        );
+        assert_eq!(session, ConsoleSession::default());
+    }
+}
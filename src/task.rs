@@ -0,0 +1,220 @@
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use bevy::prelude::*;
+
+use crate::InvocationId;
+
+/// A command which keeps running after the frame it was entered in.
+///
+/// Created with [`ConsoleCommand::start_task`](crate::ConsoleCommand::start_task). The task can
+/// be cloned and moved to other systems or into an async block on one of Bevy's task pools, and
+/// its replies are printed as they arrive. The command counts as running, and is shown next to
+/// the prompt, until [`ok`](Self::ok) or [`failed`](Self::failed) is called or every clone of the
+/// task is dropped.
+///
+/// Ctrl+C cancels the running commands. They are reported as cancelled right away, and further
+/// replies are ignored, so the work only needs to check [`is_cancelled`](Self::is_cancelled) to
+/// stop early.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy::tasks::AsyncComputeTaskPool;
+/// # use bevy_console::ConsoleCommand;
+/// # use clap::Parser;
+/// /// Counts up to a number
+/// #[derive(Parser, ConsoleCommand)]
+/// #[command(name = "count_to")]
+/// struct CountToCommand {
+///     target: u64,
+/// }
+///
+/// fn count_to_command(mut count_to: ConsoleCommand<CountToCommand>) {
+///     if let Some(Ok(CountToCommand { target })) = count_to.take() {
+///         let task = count_to.start_task();
+///         AsyncComputeTaskPool::get()
+///             .spawn(async move {
+///                 for i in 1..=target {
+///                     if task.is_cancelled() {
+///                         return;
+///                     }
+///                     task.set_progress(i as f32 / target as f32);
+///                 }
+///                 task.reply(format!("Counted to {target}"));
+///                 task.ok();
+///             })
+///             .detach();
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CommandTask {
+    shared: Arc<TaskShared>,
+}
+
+#[derive(Debug)]
+struct TaskShared {
+    name: &'static str,
+    invocation: Option<InvocationId>,
+    cancelled: AtomicBool,
+    state: Mutex<TaskState>,
+}
+
+#[derive(Debug, Default)]
+struct TaskState {
+    /// Replies not printed yet
+    replies: Vec<String>,
+    progress: Option<f32>,
+    /// Set once the task finished, `true` if it succeeded
+    succeeded: Option<bool>,
+}
+
+/// Replies and status of a task since it was last polled
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct TaskPoll {
+    pub(crate) replies: Vec<String>,
+    /// `[ok]` or `[failed]` once the task finished
+    pub(crate) status: Option<&'static str>,
+    /// The task finished, or was dropped without finishing
+    pub(crate) done: bool,
+}
+
+impl CommandTask {
+    pub(crate) fn new(name: &'static str, invocation: Option<InvocationId>) -> Self {
+        Self {
+            shared: Arc::new(TaskShared {
+                name,
+                invocation,
+                cancelled: AtomicBool::new(false),
+                state: Mutex::default(),
+            }),
+        }
+    }
+
+    /// Returns the invocation of the command, which replies are attributed to.
+    pub fn invocation(&self) -> Option<InvocationId> {
+        self.shared.invocation
+    }
+
+    /// Returns `true` once the command was cancelled, after which it should stop.
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Print a reply in the console.
+    pub fn reply(&self, msg: impl Into<String>) {
+        self.state().replies.push(msg.into());
+    }
+
+    /// Show the progress next to the prompt, from `0.0` to `1.0`.
+    pub fn set_progress(&self, progress: f32) {
+        self.state().progress = Some(progress.clamp(0.0, 1.0));
+    }
+
+    /// Finish the command, printing `[ok]` in the console.
+    pub fn ok(&self) {
+        self.state().succeeded.get_or_insert(true);
+    }
+
+    /// Finish the command, printing `[failed]` in the console.
+    pub fn failed(&self) {
+        self.state().succeeded.get_or_insert(false);
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        self.shared.name
+    }
+
+    pub(crate) fn progress(&self) -> Option<f32> {
+        self.state().progress
+    }
+
+    pub(crate) fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Takes the replies printed since the last poll.
+    ///
+    /// Must be called on the clone kept by the console, so the task is done once it is the last
+    /// one left.
+    pub(crate) fn poll(&self) -> TaskPoll {
+        let mut state = self.state();
+        let status = state
+            .succeeded
+            .map(|succeeded| if succeeded { "[ok]" } else { "[failed]" });
+        TaskPoll {
+            replies: mem::take(&mut state.replies),
+            status,
+            done: status.is_some() || Arc::strong_count(&self.shared) == 1,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, TaskState> {
+        // The state stays consistent even if a task panicked while holding the lock
+        self.shared
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Sent when a command starts running as a task, so the console can poll it
+#[derive(Event)]
+pub(crate) struct CommandTaskStarted(pub(crate) CommandTask);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poll_takes_replies_and_status() {
+        let task = CommandTask::new("load", None);
+        let worker = task.clone();
+
+        worker.reply("a");
+        worker.set_progress(2.0);
+        assert_eq!(
+            task.poll(),
+            TaskPoll {
+                replies: vec!["a".to_string()],
+                status: None,
+                done: false,
+            }
+        );
+        assert_eq!(task.progress(), Some(1.0));
+
+        worker.reply("b");
+        worker.failed();
+        worker.ok();
+        assert_eq!(
+            task.poll(),
+            TaskPoll {
+                replies: vec!["b".to_string()],
+                status: Some("[failed]"),
+                done: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_task_is_done_when_dropped() {
+        let task = CommandTask::new("load", None);
+        let worker = task.clone();
+        assert!(!task.poll().done);
+
+        worker.cancel();
+        assert!(task.is_cancelled());
+        drop(worker);
+        assert_eq!(
+            task.poll(),
+            TaskPoll {
+                replies: Vec::new(),
+                status: None,
+                done: true,
+            }
+        );
+    }
+}